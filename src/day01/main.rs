@@ -1,17 +1,11 @@
 #![allow(non_snake_case)]
 
-use std::fs;
+use std::{env, fs::File, io::{self, BufRead, BufReader}};
 
-fn part1(txt: &str) -> u32 {
-    let i = txt.lines().map(
-        |line| line.chars().filter_map(
-            |c| c.to_digit(10)
-        )
-    );
-    i.map(|j| j.clone().next().unwrap()*10
-        + j.last().unwrap()
-    )
-    .sum()
+fn calibration1(line: &str) -> u32 {
+    let mut digits = line.chars().filter_map(|c| c.to_digit(10));
+    let first = digits.next().unwrap();
+    first*10 + digits.next_back().unwrap_or(first)
 }
 
 fn replace_at(word: &str, i: usize) -> Option<String> {
@@ -35,31 +29,38 @@ fn replace_one_leading(word: &str, rev: bool) -> String {
     return word.to_string();
 }
 
-fn part2(txt: &str) -> u32 {
-    let i = txt.lines().map(
-        |line| replace_one_leading(
-            &replace_one_leading(line, false),
-            true)
-            .chars().filter_map(
-            |c| c.to_digit(10)
-        ).collect::<Vec<u32>>()
-    );
-    i.map(|j| j.first().unwrap()*10
-        + j.last().unwrap()
-    )
-    .sum()
+fn calibration2(line: &str) -> u32 {
+    calibration1(&replace_one_leading(&replace_one_leading(line, false), true))
+}
+
+fn solve(input: impl BufRead) -> (u32, u32) {
+    input.lines().fold((0, 0), |(p1, p2), line| {
+        let line = line.unwrap();
+        (p1 + calibration1(&line), p2 + calibration2(&line))
+    })
+}
+
+// First arg is the input path, or "-" for stdin
+fn open_input(dayX: &str, root: &str) -> Box<dyn BufRead> {
+    match env::args().nth(1).as_deref() {
+        Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
+        None => {
+            let path = String::from(root) + "/src/" + dayX + "/input.txt";
+            // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
+            // let path = String::from(root) + "/src/" + dayX + "/test2.txt";
+            Box::new(BufReader::new(File::open(path).unwrap()))
+        }
+    }
 }
 
 fn main() {
     let dayX = env!("CARGO_BIN_NAME");
     let root = env!("CARGO_MANIFEST_DIR");
 
-    let path = String::from(root) + "/src/" + dayX + "/input.txt";
-    // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
-    // let path = String::from(root) + "/src/" + dayX + "/test2.txt";
-    let txt = fs::read_to_string(path).unwrap();
+    let (p1, p2) = solve(open_input(dayX, root));
 
     println!("This is {}", dayX);
-    println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+    println!("Part 1: {:?}", p1);
+    println!("Part 2: {:?}", p2);
 }
@@ -1,66 +1,219 @@
 #![allow(non_snake_case)]
 
-use std::fs;
-
-
-
-fn part1(txt: &str) -> usize {
-    let maxcubes: [i64; 3] = [12, 13, 14];  // red, green, blue
-
-    txt.lines().enumerate().filter_map(
-        |(i, line)| {
-            let tokens: Vec<&str> = line.split([' ', ',', ';'])
-                                        .filter(|w| w.len() > 0)
-                                        .skip(2)
-                                        .collect();
-            tokens.chunks(2).all(|pair|
-                pair[0].parse::<i64>().unwrap() <= maxcubes[
-                    match pair[1] {
-                        "red" => 0,
-                        "green" => 1,
-                        "blue" => 2,
-                        _ => panic!("Unknown color"),
-                    }
-                ]
-            ).then_some(i+1)
+use std::{collections::HashMap, env, fs::{self, File}, io::{self, BufRead, BufReader}};
+
+type Color = String;
+
+// one handful of cubes, e.g. "3 blue, 4 red", with each color once,
+// in the order it first appears
+type Draw = Vec<(Color, u64)>;
+
+// cube limits by color; colors not listed have a limit of 0
+type Bag = HashMap<Color, u64>;
+
+// "3 blue, 4 red"; a color listed twice adds up
+fn parse_draw(s: &str) -> Draw {
+    let mut draw: Draw = vec![];
+    for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+        let (count, color) = pair.trim().split_once(' ').unwrap();
+        let (color, count) = (color.trim(), count.trim().parse::<u64>().unwrap());
+        match draw.iter_mut().find(|(c, _)| c == color) {
+            Some((_, n)) => *n += count,
+            None => draw.push((color.to_string(), count)),
+        }
+    }
+    draw
+}
+
+// "12 red, 13 green, 14 blue"
+fn parse_counts(s: &str) -> HashMap<Color, u64> {
+    parse_draw(s).into_iter().collect()
+}
+
+fn fmt_counts(counts: &HashMap<Color, u64>) -> String {
+    let mut pairs = counts.iter().collect::<Vec<_>>();
+    pairs.sort();
+    pairs.iter().map(|(c, n)| format!("{} {}", n, c)).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug)]
+struct Game {
+    id: u64,
+    draws: Vec<Draw>,
+}
+
+// the first color, in the first draw, that doesn't fit in the bag
+#[derive(Debug)]
+struct Violation {
+    draw: usize,  // 0-indexed
+    color: Color,
+    count: u64,
+    limit: u64,
+}
+
+impl Game {
+    // "Game 12: 3 blue, 4 red; 1 red, 2 green"
+    fn from_str(line: &str) -> Game {
+        let (head, rest) = line.split_once(':').unwrap();
+        let id = head.trim().strip_prefix("Game").unwrap().trim().parse::<u64>().unwrap();
+        let draws = rest.split(';').map(parse_draw).collect();
+
+        Game { id, draws }
+    }
+
+    // smallest bag that could have produced every draw
+    fn min_bag(&self) -> Bag {
+        let mut bag = Bag::new();
+        for draw in self.draws.iter() {
+            for (color, count) in draw.iter() {
+                let m = bag.entry(color.clone()).or_insert(0);
+                if *count > *m {
+                    *m = *count;
+                }
+            }
         }
-    ).sum()
-}
-
-fn part2(txt: &str) -> i64 {
-    txt.lines().map(
-        |line| {
-            let tokens: Vec<&str> = line.split([' ', ',', ';'])
-                                        .filter(|w| w.len() > 0)
-                                        .skip(2)
-                                        .collect();
-            let mut maxcubes: [i64; 3] = [0,0,0];  // red, green, blue
-            for pair in tokens.chunks(2) {
-                let cid = match pair[1] {
-                    "red" => 0,
-                    "green" => 1,
-                    "blue" => 2,
-                    _ => panic!("Unknown color"),
-                };
-                let count = pair[0].parse::<i64>().unwrap();
-                if count > maxcubes[cid] {
-                    maxcubes[cid] = count;
+        bag
+    }
+
+    fn first_violation(&self, bag: &Bag) -> Option<Violation> {
+        for (i, draw) in self.draws.iter().enumerate() {
+            for (color, count) in draw.iter() {
+                let limit = *bag.get(color).unwrap_or(&0);
+                if *count > limit {
+                    return Some(Violation { draw: i, color: color.clone(), count: *count, limit });
                 }
             }
-            maxcubes.iter().product::<i64>()
         }
-    ).sum()
+        None
+    }
+
+    fn possible_with(&self, bag: &Bag) -> bool {
+        self.first_violation(bag).is_none()
+    }
+}
+
+fn part1(game: &Game, bag: &Bag) -> u64 {
+    if game.possible_with(bag) { game.id } else { 0 }
+}
+
+// the colors the puzzle's power is taken over
+const COLORS: [&str; 3] = ["red", "green", "blue"];
+
+// product of the bag's counts over these colors; a color that's
+// missing from the bag counts as 0, as in the puzzle
+fn power(bag: &Bag, colors: &[&str]) -> u64 {
+    colors.iter().map(|&c| *bag.get(c).unwrap_or(&0)).product()
+}
+
+fn part2(game: &Game) -> u64 {
+    power(&game.min_bag(), &COLORS)
+}
+
+fn solve(input: impl BufRead) -> (u64, u64) {
+    let bag = parse_counts("12 red, 13 green, 14 blue");
+    input.lines().fold((0, 0), |(p1, p2), line| {
+        let game = Game::from_str(&line.unwrap());
+        (p1 + part1(&game, &bag), p2 + part2(&game))
+    })
+}
+
+#[derive(Debug, Default)]
+struct BagStats {
+    ngames: u64,
+    npossible: u64,
+    id_sum: u64,
+    power_sum: u64,
+    max_min_bag: Bag,  // per color, the largest min_bag over all games
+    violations: HashMap<Color, u64>,  // impossible games, by offending color
+}
+
+// Check every game against the bag, printing one line per game,
+// then the aggregate statistics
+fn bag_report(input: impl BufRead, bag: &Bag) {
+    let mut stats = BagStats::default();
+    let colors = bag.keys().map(String::as_str).collect::<Vec<_>>();
+
+    println!("Bag: {}", fmt_counts(bag));
+    for line in input.lines() {
+        let game = Game::from_str(&line.unwrap());
+        let min_bag = game.min_bag();
+
+        stats.ngames += 1;
+        stats.power_sum += power(&min_bag, &colors);
+        for (color, &count) in min_bag.iter() {
+            let m = stats.max_min_bag.entry(color.clone()).or_insert(0);
+            *m = (*m).max(count);
+        }
+
+        match game.first_violation(bag) {
+            None => {
+                stats.npossible += 1;
+                stats.id_sum += game.id;
+                println!("Game {}: possible; min bag {}", game.id, fmt_counts(&min_bag));
+            }
+            Some(v) => {
+                *stats.violations.entry(v.color.clone()).or_insert(0) += 1;
+                println!("Game {}: impossible, draw {} has {} {} > {}; min bag {}",
+                    game.id, v.draw + 1, v.count, v.color, v.limit, fmt_counts(&min_bag));
+            }
+        }
+    }
+
+    println!("Possible: {} of {} games, id sum {}", stats.npossible, stats.ngames, stats.id_sum);
+    println!("Sum of min bag powers over the bag's colors: {}", stats.power_sum);
+    println!("Largest min bag: {}", fmt_counts(&stats.max_min_bag));
+    println!("Impossible games by first offending color: {}",
+        fmt_counts(&stats.violations));
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    input: Option<String>,  // path, or "-" for stdin
+    bag: Option<Bag>,
+}
+
+// day02 [input] [--bag "12 red, 13 green, 14 blue" | --bag-file bag.txt]
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut it = env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--bag" => args.bag = Some(parse_counts(&it.next().expect("--bag needs a spec"))),
+            "--bag-file" => {
+                let spec = fs::read_to_string(it.next().expect("--bag-file needs a path")).unwrap();
+                args.bag = Some(parse_counts(&spec.lines().collect::<Vec<_>>().join(",")));
+            }
+            _ => args.input = Some(a),
+        }
+    }
+    args
+}
+
+fn open_input(dayX: &str, root: &str, input: Option<&str>) -> Box<dyn BufRead> {
+    match input {
+        Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
+        None => {
+            let path = String::from(root) + "/src/" + dayX + "/input.txt";
+            // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
+            Box::new(BufReader::new(File::open(path).unwrap()))
+        }
+    }
 }
 
 fn main() {
     let dayX = env!("CARGO_BIN_NAME");
     let root = env!("CARGO_MANIFEST_DIR");
 
-    let path = String::from(root) + "/src/" + dayX + "/input.txt";
-    // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
-    let txt = fs::read_to_string(path).unwrap();
+    let args = parse_args();
+    let input = open_input(dayX, root, args.input.as_deref());
 
     println!("This is {}", dayX);
-    println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+    if let Some(bag) = args.bag {
+        bag_report(input, &bag);
+    } else {
+        let (p1, p2) = solve(input);
+        println!("Part 1: {:?}", p1);
+        println!("Part 2: {:?}", p2);
+    }
 }
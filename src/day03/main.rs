@@ -1,123 +1,207 @@
 #![allow(non_snake_case)]
 
-use std::{fs, collections::{HashMap, HashSet}};
+use std::{env, fs, collections::HashMap, ops::RangeInclusive};
+
+// a run of digits on one row; cols are inclusive
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    row: usize,
+    start: usize,
+    end: usize,
+    value: i64,
+}
 
-#[derive(Debug)]
-struct Grid {
-    grid: Vec<i64>,
-    Nx: usize,
-    Ny: usize,
-    valmap: HashMap<i64, i64>,
+#[derive(Debug, Clone, Copy)]
+struct Symbol {
+    row: usize,
+    col: usize,
+    c: char,
 }
 
-const DOT: i64 = 0;
-const SYM: i64 = 1;
-const GEAR: i64 = 2;
+// Index of the numbers and symbols in a schematic, with the adjacency
+// between them computed once up front
+#[derive(Debug)]
+struct Schematic {
+    numbers: Vec<Span>,
+    symbols: Vec<Symbol>,
+    sym_nums: Vec<Vec<usize>>,  // for each symbol, indices of adjacent numbers
+    num_syms: Vec<Vec<usize>>,  // for each number, indices of adjacent symbols
+}
 
-impl Grid {
+impl Schematic {
     fn from_txt(txt: &str) -> Self {
-        let mut grid = Self {
-            Nx: txt.lines().count(),
-            Ny: txt.lines().next().unwrap().len(),
-            grid: vec![],
-            valmap: HashMap::new(),
-        };
-        grid.grid = vec![DOT; grid.Nx * grid.Ny];
-
-        let mut key = GEAR + 1;
-        for (j,line) in txt.lines().enumerate() {
-            let mut i = 0;
+        let mut numbers = vec![];
+        let mut symbols = vec![];
+        for (row, line) in txt.lines().enumerate() {
             let chars: Vec<char> = line.chars().collect();
-            while i < line.len() {
-                let c = chars[i];
-                if c.is_digit(10) {
-                    let len: usize = chars[i..].iter().position(|c| !c.is_digit(10)).unwrap_or(line.len() - i);
-                    grid.grid[(grid.Nx*j + i)..(grid.Nx*j + i + len)].fill(key);
-                    grid.valmap.insert(key, chars[i..(i+len)].iter().collect::<String>().parse::<i64>().unwrap());
-                    key += 1;
-                    i += len;
-                }
-                else if c == '*' {
-                    grid.grid[grid.Nx*j + i] = GEAR;
-                    i += 1;
-                }
-                else if c != '.' {
-                    grid.grid[grid.Nx*j + i] = SYM;
-                    i += 1;
-                }
-                else {
-                    i += 1;
+            let mut col = 0;
+            while col < chars.len() {
+                let c = chars[col];
+                if c.is_ascii_digit() {
+                    let len = chars[col..].iter().position(|c| !c.is_ascii_digit()).unwrap_or(chars.len() - col);
+                    numbers.push(Span {
+                        row,
+                        start: col,
+                        end: col + len - 1,
+                        value: chars[col..(col+len)].iter().collect::<String>().parse::<i64>().unwrap(),
+                    });
+                    col += len;
+                } else {
+                    if c != '.' {
+                        symbols.push(Symbol { row, col, c });
+                    }
+                    col += 1;
                 }
             }
         }
-        grid
-    }
-
-    fn get(&self, i: i64, j: i64) -> Option<i64> {
-        if i < 0 || j < 0 || i >= self.Nx as i64 || j >= self.Ny as i64 {
-            None
-        }
-        else {
-            Some(self.grid[self.Nx*j as usize + i as usize])
-        }
-    }
 
-    // neighbor part numbers
-    fn neighbors(&mut self, i: usize, j: usize) -> Vec<i64> {
-        let mut keyset = HashSet::new();
-        for dj in -1..=1 {
-            for di in -1..=1 {
-                if di == 0 && dj == 0 {
-                    continue;
-                }
-                if let Some(k) = self.get(i as i64 + di, j as i64 + dj) {
-                    if k > GEAR {
-                        keyset.insert(k);
+        let sym_at: HashMap<(usize, usize), usize> = symbols.iter()
+            .enumerate()
+            .map(|(k, s)| ((s.row, s.col), k))
+            .collect();
+
+        // walk the ring of cells around each number
+        let mut sym_nums = vec![vec![]; symbols.len()];
+        let mut num_syms = vec![vec![]; numbers.len()];
+        for (n, span) in numbers.iter().enumerate() {
+            for row in span.row.saturating_sub(1)..=(span.row + 1) {
+                for col in span.start.saturating_sub(1)..=(span.end + 1) {
+                    if let Some(&s) = sym_at.get(&(row, col)) {
+                        sym_nums[s].push(n);
+                        num_syms[n].push(s);
                     }
                 }
             }
         }
-        let neighbors = keyset.iter()
-                        .map(|k| *self.valmap.get(k).unwrap())
-                        .collect::<Vec<i64>>();
-        neighbors
+
+        Self { numbers, symbols, sym_nums, num_syms }
+    }
+
+    fn numbers_adjacent_to_symbol(&self, s: usize) -> impl Iterator<Item = &Span> {
+        self.sym_nums[s].iter().map(|&n| &self.numbers[n])
+    }
+
+    fn symbols_adjacent_to_number(&self, n: usize) -> impl Iterator<Item = &Symbol> {
+        self.num_syms[n].iter().map(|&s| &self.symbols[s])
+    }
+
+    // numbers touching at least one symbol
+    fn part_numbers(&self) -> impl Iterator<Item = &Span> {
+        self.numbers.iter().enumerate()
+            .filter(|(n, _)| self.symbols_adjacent_to_number(*n).next().is_some())
+            .map(|(_, span)| span)
+    }
+
+    // numbers touching no symbol
+    fn isolated_numbers(&self) -> impl Iterator<Item = &Span> {
+        self.numbers.iter().enumerate()
+            .filter(|(n, _)| self.num_syms[*n].is_empty())
+            .map(|(_, span)| span)
     }
 }
 
 fn part1(txt: &str) -> i64 {
-    let mut grid = Grid::from_txt(txt);
-    // println!("{:?}", grid);
-
-    let mut sum = 0;
-    for j in 0..grid.Ny {
-        for i in 0..grid.Nx {
-            if let SYM | GEAR = grid.grid[grid.Nx*j + i] {
-                sum += grid.neighbors(i, j).iter().sum::<i64>();
-            }
+    let schem = Schematic::from_txt(txt);
+    // println!("{:?}", schem);
+
+    schem.part_numbers().map(|span| span.value).sum()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Product,
+    Sum,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(&self, vals: &[i64]) -> i64 {
+        match self {
+            Aggregate::Product => vals.iter().product(),
+            Aggregate::Sum => vals.iter().sum(),
+            Aggregate::Max => *vals.iter().max().unwrap_or(&0),
         }
     }
+}
 
-    sum
+// Which symbols count as gears, how many part numbers they must touch,
+// and how those numbers combine into the gear ratio
+#[derive(Debug, Clone)]
+struct GearRule {
+    symbols: Vec<char>,
+    neighbors: RangeInclusive<usize>,
+    agg: Aggregate,
 }
 
-fn part2(txt: &str) -> i64 {
-    let mut grid = Grid::from_txt(txt);
-    // println!("{:?}", grid);
-
-    let mut sum = 0;
-    for j in 0..grid.Ny {
-        for i in 0..grid.Nx {
-            if grid.grid[grid.Nx*j + i] == GEAR {
-                let neigh = grid.neighbors(i, j);
-                // println!("{:?}", neigh);
-                if neigh.len() == 2 {
-                    sum += neigh[0] * neigh[1];
-                }
+impl GearRule {
+    // the puzzle's rule: '*' touching exactly two numbers, multiplied
+    fn standard() -> Self {
+        Self { symbols: vec!['*'], neighbors: 2..=2, agg: Aggregate::Product }
+    }
+
+    // "SYMBOLS:MIN[-MAX]:product|sum|max", e.g. "*:2:product" or "#$:1-3:sum"
+    fn from_str(s: &str) -> Self {
+        let mut fields = s.split(':');
+        let symbols = fields.next().unwrap().chars().collect();
+        let count = fields.next().unwrap();
+        let neighbors = match count.split_once('-') {
+            Some((lo, hi)) => lo.parse::<usize>().unwrap()..=hi.parse::<usize>().unwrap(),
+            None => {
+                let n = count.parse::<usize>().unwrap();
+                n..=n
             }
-        }
+        };
+        let agg = match fields.next().unwrap() {
+            "product" => Aggregate::Product,
+            "sum" => Aggregate::Sum,
+            "max" => Aggregate::Max,
+            a => panic!("Unknown aggregation {}", a),
+        };
+        Self { symbols, neighbors, agg }
+    }
+}
+
+#[derive(Debug)]
+struct Gear {
+    symbol: Symbol,
+    parts: Vec<i64>,
+    ratio: i64,
+}
+
+// Every symbol that qualifies under the first rule listing its char
+fn find_gears(schem: &Schematic, rules: &[GearRule]) -> Vec<Gear> {
+    schem.symbols.iter().enumerate().filter_map(|(s, sym)| {
+        let rule = rules.iter().find(|r| r.symbols.contains(&sym.c))?;
+        let parts = schem.numbers_adjacent_to_symbol(s).map(|span| span.value).collect::<Vec<i64>>();
+        rule.neighbors.contains(&parts.len()).then(|| Gear {
+            symbol: *sym,
+            ratio: rule.agg.apply(&parts),
+            parts,
+        })
+    }).collect()
+}
+
+fn gear_report(gears: &[Gear]) {
+    for g in gears {
+        println!("'{}' at row {}, col {}: parts {:?} -> {}",
+            g.symbol.c, g.symbol.row + 1, g.symbol.col + 1, g.parts, g.ratio);
     }
+    println!("{} gears, total {}", gears.len(), gears.iter().map(|g| g.ratio).sum::<i64>());
+}
+
+// numbers that touch no symbol, so aren't part numbers
+fn isolated_report(schem: &Schematic) {
+    let isolated = schem.isolated_numbers().collect::<Vec<_>>();
+    for n in isolated.iter() {
+        println!("{} at row {}, cols {}-{}", n.value, n.row + 1, n.start + 1, n.end + 1);
+    }
+    println!("{} isolated numbers, total {}", isolated.len(), isolated.iter().map(|n| n.value).sum::<i64>());
+}
 
-    sum
+fn part2(txt: &str, rules: &[GearRule]) -> i64 {
+    let schem = Schematic::from_txt(txt);
+
+    find_gears(&schem, rules).iter().map(|g| g.ratio).sum()
 }
 
 fn main() {
@@ -130,5 +214,24 @@ fn main() {
 
     println!("This is {}", dayX);
     println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+
+    // --isolated lists the numbers next to no symbol
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|a| a == "--isolated") {
+        isolated_report(&Schematic::from_txt(&txt));
+    }
+
+    // --gear RULE (repeatable) replaces the standard rule; --report lists every gear
+    let mut rules: Vec<GearRule> = args.windows(2)
+        .filter(|w| w[0] == "--gear")
+        .map(|w| GearRule::from_str(&w[1]))
+        .collect();
+    if rules.is_empty() {
+        rules.push(GearRule::standard());
+    }
+
+    if args.iter().any(|a| a == "--report") {
+        gear_report(&find_gears(&Schematic::from_txt(&txt), &rules));
+    }
+    println!("Part 2: {:?}", part2(&txt, &rules));
 }
@@ -1,41 +1,309 @@
 #![allow(non_snake_case)]
 
-use std::fs;
+use std::{collections::{HashSet, VecDeque}, env, fs::File, io::{self, BufRead, BufReader}};
 
-fn part1(txt: &str) -> i64 {
-    txt.lines().map(|l| {
-        let mut c = l.split([':', '|']).skip(1).map(|s| s.split_whitespace());
-        let left = c.next().unwrap();
-        let right = c.next().unwrap();
-        let count = left.filter(|n| right.clone().find(|s| s == n).is_some()).count() as u32;
-        if count >= 1 { 2i64.pow(count - 1) } else { 0 }
-    }).sum()
+#[derive(Debug)]
+struct Scratchcard {
+    id: usize,
+    winning: HashSet<u32>,
+    have: Vec<u32>,
 }
 
-fn part2(txt: &str) -> usize {
-    let mut nmatch = txt.lines().map(|l| {
-        let mut c = l.split([':', '|']).skip(1).map(|s| s.split_whitespace());
-        let left = c.next().unwrap();
-        let right = c.next().unwrap();
-        left.filter(|n| right.clone().find(|s| s == n).is_some()).count()
-    }).collect::<Vec<usize>>();
+impl Scratchcard {
+    // "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53"
+    fn from_str(line: &str) -> Self {
+        let (head, rest) = line.split_once(':').unwrap();
+        let (winning, have) = rest.split_once('|').unwrap();
+        Self {
+            id: head.strip_prefix("Card").unwrap().trim().parse::<usize>().unwrap(),
+            winning: winning.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect(),
+            have: have.split_whitespace().map(|n| n.parse::<u32>().unwrap()).collect(),
+        }
+    }
+
+    fn nmatch(&self) -> usize {
+        self.have.iter().filter(|n| self.winning.contains(n)).count()
+    }
+}
+
+// Points for a card with this many matches
+type Scoring = fn(usize) -> i64;
+
+// the puzzle's rule: 1 point, doubled for each match after the first
+fn doubling(count: usize) -> i64 {
+    if count >= 1 { 2i64.pow(count as u32 - 1) } else { 0 }
+}
+
+fn linear(count: usize) -> i64 {
+    count as i64
+}
+
+// Which cards a winning card hands copies to
+#[derive(Debug, Clone, Copy)]
+enum Propagation {
+    // the puzzle's rule: the next N cards; copies past the end of the table are lost
+    NextN,
+    // the next N cards, wrapping around to the start of the table.
+    // Copies that wrap around win copies of their own, so nothing is
+    // settled until the whole table has been seen.
+    Wrap,
+    // the next N cards, but no card can hold more than this many copies
+    Capped(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    score: Scoring,
+    copies: Propagation,
+}
 
-    for i in (0..nmatch.len()).rev() {
-        nmatch[i] += nmatch[(i+1)..(i + nmatch[i] + 1)].iter().sum::<usize>();
+impl Default for Rules {
+    fn default() -> Self {
+        Self { score: doubling, copies: Propagation::NextN }
     }
+}
+
+// How many copies of a card we ended up with, and which earlier
+// cards won them (card id, copies won from that card)
+#[derive(Debug)]
+struct LedgerEntry {
+    id: usize,
+    copies: usize,
+    won_from: Vec<(usize, usize)>,
+}
 
-    nmatch.iter().sum::<usize>() + nmatch.len()
+// Copies won by earlier cards are kept in a queue that only looks as far
+// ahead as the largest match count, so cards can be streamed through and
+// each card's entry is final as soon as it is pushed. Wrapping copies can
+// land on any card, so then the cards are buffered and settled at the end.
+#[derive(Debug)]
+struct Ledger {
+    copies: Propagation,
+    pending: VecDeque<Vec<(usize, usize)>>,
+    buffered: Vec<(usize, usize)>,  // (card id, matches), only when wrapping
+}
+
+impl Ledger {
+    fn new(copies: Propagation) -> Self {
+        Self { copies, pending: VecDeque::new(), buffered: vec![] }
+    }
+
+    // The card's entry, or None if it can't be known until finish
+    fn push(&mut self, card: &Scratchcard) -> Option<LedgerEntry> {
+        if let Propagation::Wrap = self.copies {
+            self.buffered.push((card.id, card.nmatch()));
+            return None;
+        }
+
+        let won_from = self.pending.pop_front().unwrap_or_default();
+        let mut copies = 1 + won_from.iter().map(|(_, n)| n).sum::<usize>();
+        if let Propagation::Capped(max) = self.copies {
+            copies = copies.min(max);
+        }
+
+        let count = card.nmatch();
+        if self.pending.len() < count {
+            self.pending.resize(count, vec![]);
+        }
+        for p in self.pending.iter_mut().take(count) {
+            p.push((card.id, copies));
+        }
+
+        Some(LedgerEntry { id: card.id, copies, won_from })
+    }
+
+    // The entries held back by push. Copies that run off the end of the
+    // table are dropped, unless they wrap around. Then each card's copies
+    // depend on the cards that win copies of it, which is only finite if
+    // no card can (eventually) win a copy of itself.
+    fn finish(self) -> Result<Vec<LedgerEntry>, String> {
+        let cards = self.buffered;
+        let n = cards.len();
+
+        // settle cards in topological order of "wins copies of"
+        let mut nwinners = vec![0; n];
+        for (k, &(_, count)) in cards.iter().enumerate() {
+            for d in 1..=count {
+                nwinners[(k + d) % n] += 1;
+            }
+        }
+        let mut ready = (0..n).filter(|&k| nwinners[k] == 0).collect::<VecDeque<_>>();
+        let mut entries = cards.iter()
+            .map(|&(id, _)| LedgerEntry { id, copies: 1, won_from: vec![] })
+            .collect::<Vec<_>>();
+        let mut nsettled = 0;
+        while let Some(k) = ready.pop_front() {
+            nsettled += 1;
+            let (id, count) = cards[k];
+            let copies = entries[k].copies;
+            for d in 1..=count {
+                let j = (k + d) % n;
+                entries[j].copies += copies;
+                entries[j].won_from.push((id, copies));
+                nwinners[j] -= 1;
+                if nwinners[j] == 0 {
+                    ready.push_back(j);
+                }
+            }
+        }
+        if nsettled < n {
+            let k = (0..n).find(|&k| nwinners[k] > 0).unwrap();
+            return Err(format!("Card {} wins copies of itself around the wrap, so the copies never end", cards[k].0));
+        }
+
+        for e in entries.iter_mut() {
+            e.won_from.sort();
+        }
+        Ok(entries)
+    }
+}
+
+fn print_entry(entry: &LedgerEntry) {
+    let from = entry.won_from.iter()
+        .map(|(id, n)| format!("{} from card {}", n, id))
+        .collect::<Vec<_>>().join(", ");
+    println!("Card {}: {} copies (original{}{})", entry.id, entry.copies,
+        if from.is_empty() { "" } else { ", " }, from);
+}
+
+fn solve(input: impl BufRead, rules: Rules, show_ledger: bool) -> Result<(i64, usize), String> {
+    let mut p1 = 0;
+    let mut ncards = 0;
+    let mut ledger = Ledger::new(rules.copies);
+
+    for line in input.lines() {
+        let card = Scratchcard::from_str(&line.unwrap());
+        p1 += (rules.score)(card.nmatch());
+
+        if let Some(entry) = ledger.push(&card) {
+            ncards += entry.copies;
+            if show_ledger {
+                print_entry(&entry);
+            }
+        }
+    }
+
+    for entry in ledger.finish()? {
+        ncards += entry.copies;
+        if show_ledger {
+            print_entry(&entry);
+        }
+    }
+
+    Ok((p1, ncards))
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    input: Option<String>,  // path, or "-" for stdin
+    rules: Rules,
+    show_ledger: bool,
+}
+
+// day04 [input] [--ledger] [--score doubling|linear] [--copies next|wrap|cap=N]
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut it = env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--ledger" => args.show_ledger = true,
+            "--score" => args.rules.score = match it.next().expect("--score needs a rule").as_str() {
+                "doubling" => doubling,
+                "linear" => linear,
+                s => panic!("Unknown scoring {}", s),
+            },
+            "--copies" => args.rules.copies = match it.next().expect("--copies needs a rule").as_str() {
+                "next" => Propagation::NextN,
+                "wrap" => Propagation::Wrap,
+                s => Propagation::Capped(
+                    s.strip_prefix("cap=").expect("Unknown copy rule").parse::<usize>().unwrap()
+                ),
+            },
+            _ => args.input = Some(a),
+        }
+    }
+    args
+}
+
+fn open_input(dayX: &str, root: &str, input: Option<&str>) -> Box<dyn BufRead> {
+    match input {
+        Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
+        None => {
+            let path = String::from(root) + "/src/" + dayX + "/input.txt";
+            // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
+            Box::new(BufReader::new(File::open(path).unwrap()))
+        }
+    }
 }
 
 fn main() {
     let dayX = env!("CARGO_BIN_NAME");
     let root = env!("CARGO_MANIFEST_DIR");
 
-    let path = String::from(root) + "/src/" + dayX + "/input.txt";
-    // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
-    let txt = fs::read_to_string(path).unwrap();
+    let args = parse_args();
 
     println!("This is {}", dayX);
-    println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+    match solve(open_input(dayX, root, args.input.as_deref()), args.rules, args.show_ledger) {
+        Ok((p1, p2)) => {
+            println!("Part 1: {:?}", p1);
+            println!("Part 2: {:?}", p2);
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the cascade from the puzzle text
+    #[test]
+    fn example_cascade() {
+        let mut ledger = Ledger::new(Propagation::NextN);
+        let entries = include_str!("test1.txt").lines()
+            .map(|line| ledger.push(&Scratchcard::from_str(line)).unwrap())
+            .collect::<Vec<_>>();
+        assert!(ledger.finish().unwrap().is_empty());
+
+        let copies = entries.iter().map(|e| e.copies).collect::<Vec<_>>();
+        assert_eq!(copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(copies.iter().sum::<usize>(), 30);
+
+        assert_eq!(entries[0].won_from, vec![]);
+        assert_eq!(entries[1].won_from, vec![(1, 1)]);
+        assert_eq!(entries[3].won_from, vec![(1, 1), (2, 2), (3, 4)]);
+        assert_eq!(entries[4].won_from, vec![(1, 1), (3, 4), (4, 8)]);
+        assert_eq!(entries[5].won_from, vec![]);
+    }
+
+    fn cascade(txt: &str, copies: Propagation) -> Result<Vec<usize>, String> {
+        let mut ledger = Ledger::new(copies);
+        let mut entries = txt.lines()
+            .filter_map(|line| ledger.push(&Scratchcard::from_str(line)))
+            .collect::<Vec<_>>();
+        entries.extend(ledger.finish()?);
+        Ok(entries.iter().map(|e| e.copies).collect())
+    }
+
+    // wrapping doesn't matter when nothing runs off the end
+    #[test]
+    fn wrap_matches_next_on_example() {
+        let txt = include_str!("test1.txt");
+        assert_eq!(cascade(txt, Propagation::Wrap), cascade(txt, Propagation::NextN));
+    }
+
+    // card 3 wins copies of cards 1 and 2, and those go on to win more
+    #[test]
+    fn wrapped_copies_cascade() {
+        let txt = "Card 1: 1 | 1\nCard 2: 9 | 8\nCard 3: 1 2 | 1 2";
+        assert_eq!(cascade(txt, Propagation::Wrap).unwrap(), vec![2, 4, 1]);
+        assert_eq!(cascade(txt, Propagation::NextN).unwrap(), vec![1, 2, 1]);
+    }
+
+    #[test]
+    fn wrap_cycle_is_an_error() {
+        let txt = "Card 1: 1 | 1\nCard 2: 1 | 1";
+        assert!(cascade(txt, Propagation::Wrap).is_err());
+    }
 }
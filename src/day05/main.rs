@@ -1,6 +1,6 @@
 #![allow(non_snake_case)]
 
-use std::{fs, ops::Range, cmp, vec};
+use std::{env, fs, fmt, ops::Range, cmp, collections::HashMap};
 
 #[derive(Debug, Clone)]
 struct RangePair {
@@ -15,55 +15,143 @@ impl RangePair {
             source: source_start..source_start+count,
         }
     }
+}
 
-    fn intersect(&self, other: &Range<i64>) -> Vec<Option<Range<i64>>> {
-        let mut ranges = vec![];
-        ranges.push(
-            if other.start < self.source.start {
-                // unmapped region before self.source
-                Some(other.start..cmp::min(other.end, self.source.start))
-            } else {
-                None
-            }
-        );
-        ranges.push(
-            if other.end > self.source.end {
-                // unmapped region after self.source
-                Some(cmp::max(other.start, self.source.end)..other.end)
-            } else {
-                None
-            }
-        );
-
-        if other.end > self.source.start &&
-            self.source.end > other.start {
-            // mapped region
-            let mut ret = self.dest.clone();
-            if self.source.start < other.start {
-                ret.start += other.start - self.source.start;
+// A piece of a piecewise-linear map: x in source maps to x + offset
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    source: Range<i64>,
+    offset: i64,
+}
+
+// Almanac numbers are non-negative, so every map is defined on this domain
+const DOMAIN: Range<i64> = 0..i64::MAX;
+
+// A total map on DOMAIN, as sorted, contiguous pieces. Numbers not covered
+// by any range in the almanac get explicit identity pieces.
+#[derive(Debug, Clone)]
+struct PiecewiseMap {
+    pieces: Vec<Piece>,
+}
+
+impl PiecewiseMap {
+    fn identity() -> Self {
+        Self { pieces: vec![Piece { source: DOMAIN, offset: 0 }] }
+    }
+
+    fn from_pairs(pairs: &[RangePair]) -> Self {
+        let mut sorted = pairs.to_vec();
+        sorted.sort_by_key(|p| p.source.start);
+
+        let mut pieces = vec![];
+        let mut x = DOMAIN.start;
+        for p in sorted.iter().filter(|p| !p.source.is_empty()) {
+            if p.source.start > x {
+                pieces.push(Piece { source: x..p.source.start, offset: 0 });
             }
-            if self.source.end > other.end {
-                ret.end -= self.source.end - other.end;
+            pieces.push(Piece { source: p.source.clone(), offset: p.dest.start - p.source.start });
+            x = p.source.end;
+        }
+        if x < DOMAIN.end {
+            pieces.push(Piece { source: x..DOMAIN.end, offset: 0 });
+        }
+
+        Self::merged(pieces)
+    }
+
+    // join neighbors with the same offset
+    fn merged(pieces: Vec<Piece>) -> Self {
+        let mut out: Vec<Piece> = vec![];
+        for p in pieces {
+            match out.last_mut() {
+                Some(last) if last.offset == p.offset && last.source.end == p.source.start => {
+                    last.source.end = p.source.end;
+                }
+                _ => out.push(p),
             }
+        }
+        Self { pieces: out }
+    }
 
-            ranges.push(Some(ret));
+    // index of the piece containing x
+    fn find(&self, x: i64) -> usize {
+        self.pieces.partition_point(|p| p.source.end <= x)
+    }
+
+    fn apply(&self, x: i64) -> i64 {
+        x + self.pieces[self.find(x)].offset
+    }
+
+    // images of each piece the range passes through, in source order
+    fn apply_range(&self, range: &Range<i64>) -> Vec<Range<i64>> {
+        self.pieces[self.find(range.start)..].iter()
+            .take_while(|p| p.source.start < range.end)
+            .map(|p| {
+                let start = cmp::max(p.source.start, range.start);
+                let end = cmp::min(p.source.end, range.end);
+                (start + p.offset)..(end + p.offset)
+            })
+            .collect()
+    }
+
+    // Every source range that maps into target, sorted and merged.
+    // Pieces can overlap in their images, so this scans them all.
+    fn preimage(&self, target: &Range<i64>) -> Vec<Range<i64>> {
+        let mut ranges = self.pieces.iter().filter_map(|p| {
+            let start = cmp::max(p.source.start + p.offset, target.start);
+            let end = cmp::min(p.source.end + p.offset, target.end);
+            (start < end).then(|| (start - p.offset)..(end - p.offset))
+        }).collect::<Vec<_>>();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<i64>> = vec![];
+        for r in ranges {
+            match merged.last_mut() {
+                Some(last) if last.end >= r.start => last.end = cmp::max(last.end, r.end),
+                _ => merged.push(r),
+            }
         }
-        else {
-            ranges.push(None);
+        merged
+    }
+
+    // self, followed by then
+    fn compose(&self, then: &PiecewiseMap) -> Self {
+        let mut pieces = vec![];
+        for p in self.pieces.iter() {
+            let mut x = p.source.start;
+            for img in then.apply_range(&((p.source.start + p.offset)..(p.source.end + p.offset))) {
+                let len = img.end - img.start;
+                pieces.push(Piece { source: x..x+len, offset: img.start - x });
+                x += len;
+            }
         }
-        ranges
+        Self::merged(pieces)
     }
+}
 
+impl fmt::Display for PiecewiseMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for p in self.pieces.iter() {
+            writeln!(f, "{:>20} {:>20} -> {:>20} {:>20}  ({:+})",
+                p.source.start, p.source.end,
+                p.source.start + p.offset, p.source.end + p.offset,
+                p.offset)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Almanac {
     seeds: Vec<i64>,
-    maps: Vec<Vec<RangePair>>,
+    categories: Vec<String>,  // in chain order, starting with "seed"
+    maps: Vec<Vec<RangePair>>,  // maps[i] takes categories[i] to categories[i+1]
 }
 
 impl Almanac {
-    fn new(txt: &str) -> Self {
+    // Sections can come in any order; they're chained by their
+    // "source-to-dest map:" headers, starting from "seed"
+    fn new(txt: &str) -> Result<Self, String> {
         let mut lines = txt.lines();
         let seeds = lines.next()
         .unwrap()
@@ -72,11 +160,15 @@ impl Almanac {
         .map(|x| x.parse::<i64>().unwrap())
         .collect::<Vec<i64>>();
 
-        let sections = txt.split("\n\n").skip(1);
-        let maps = sections.map(
-            |sec| sec.split("\n")
-            .skip(1)
-            .filter(|line| line.len() > 0)
+        let mut by_source: HashMap<String, (String, Vec<RangePair>)> = HashMap::new();
+        for sec in txt.split("\n\n").skip(1) {
+            let mut sec_lines = sec.lines();
+            let header = sec_lines.next().unwrap();
+            let (source, dest) = header.strip_suffix(" map:")
+                .and_then(|h| h.split_once("-to-"))
+                .ok_or(format!("Bad map header: {}", header))?;
+            let pairs = sec_lines
+            .filter(|line| !line.is_empty())
             .map(
                 |line| {
                     let nums = line.split_whitespace()
@@ -85,78 +177,103 @@ impl Almanac {
                     ).collect::<Vec<i64>>();
                     RangePair::new(nums[0], nums[1], nums[2])
                 }
-            ).collect()
-        ).collect();
+            ).collect();
+            if by_source.insert(source.to_string(), (dest.to_string(), pairs)).is_some() {
+                return Err(format!("More than one map from {}", source));
+            }
+        }
 
-        Self {
+        let mut categories = vec![String::from("seed")];
+        let mut maps = vec![];
+        while let Some((dest, pairs)) = by_source.remove(categories.last().unwrap()) {
+            if categories.contains(&dest) {
+                return Err(format!("Cycle in map chain: {} -> {}", categories.join(" -> "), dest));
+            }
+            categories.push(dest);
+            maps.push(pairs);
+        }
+        if !by_source.is_empty() {
+            let mut orphans = by_source.keys().cloned().collect::<Vec<_>>();
+            orphans.sort();
+            return Err(format!("Gap in map chain after {}: no path to the maps from {}",
+                categories.last().unwrap(), orphans.join(", ")));
+        }
+
+        Ok(Self {
             seeds,
+            categories,
             maps
-        }
+        })
     }
 
-    fn walk(&self, m: usize, range: Range<i64>) -> Vec<Range<i64>> {
-        if m == self.maps.len() {
-            return vec![range];
-        }
-        let mut ret: Vec<Range<i64>> = vec![];
-        let mut unmatched = vec![range.clone()];
-        let mut next_unmatched = vec![];
-        for pair in &self.maps[m] {
-            for r in unmatched.iter() {
-                let ranges = pair.intersect(&r);
-                if ranges.len() > 0 {
-                    for unmatched_r in ranges[0..=1].iter() {
-                        if let Some(ur) = unmatched_r {
-                            next_unmatched.push(ur.clone());
-                        }
-                    }
-                    if let Some(mr) = &ranges[2] {
-                        ret.push(mr.clone());
-                    }
-                }
-            }
-            unmatched = next_unmatched;
-            next_unmatched = vec![];
-        }
+    fn category(&self, name: &str) -> Result<usize, String> {
+        self.categories.iter().position(|c| c == name)
+            .ok_or(format!("Unknown category {}", name))
+    }
 
-        ret.extend(unmatched);
+    // all the layers, seed to location
+    fn compose(&self) -> PiecewiseMap {
+        self.compose_between(0, self.maps.len())
+    }
 
-        ret.dedup();  // supposed to happen...?
-        ret.iter()
-            .flat_map(|r| self.walk(m+1, r.clone()))
-            .collect()
+    // Layers are numbered by category index: 0 is seed, maps.len() is the
+    // end of the chain. `to` must not come before `from`.
+    fn compose_between(&self, from: usize, to: usize) -> PiecewiseMap {
+        self.maps[from..to].iter()
+            .fold(PiecewiseMap::identity(), |acc, m| acc.compose(&PiecewiseMap::from_pairs(m)))
+    }
+
+    // e.g. map_between("soil", "humidity")
+    fn map_between(&self, from: &str, to: &str) -> Result<PiecewiseMap, String> {
+        let (i, j) = (self.category(from)?, self.category(to)?);
+        if j < i {
+            return Err(format!("{} comes after {} in the chain", from, to));
+        }
+        Ok(self.compose_between(i, j))
+    }
+
+    // all the ranges in category `from` that land in target in category `to`
+    fn preimage(&self, from: &str, to: &str, target: &Range<i64>) -> Result<Vec<Range<i64>>, String> {
+        Ok(self.map_between(from, to)?.preimage(target))
     }
 
     fn seed_ranges(&self) -> Vec<Range<i64>> {
-        self.seeds.iter()
-        // .take(1)  // debug
-        .flat_map(|&s|
-            self.walk(0, s..s+1)
-        )
-        .collect()
-    }
-
-    fn seed_ranges_p2(&self) -> Vec<Range<i64>> {
-        self.seeds
-        .chunks(2)
-        // .take(1)  // debug
-        .flat_map(|s: &[i64]|
-            self.walk(0, s[0]..s[0]+s[1])
-        )
-        .collect()
+        self.seeds.chunks(2).map(|s| s[0]..s[0]+s[1]).collect()
     }
 }
 
-fn part1(txt: &str) -> i64 {
-    let almanac = Almanac::new(txt);
-    let seed_ranges = almanac.seed_ranges();
-    seed_ranges.iter().map(|r| r.start).min().unwrap()
+fn part1(almanac: &Almanac, seed_to_loc: &PiecewiseMap) -> i64 {
+    almanac.seeds.iter().map(|&s| seed_to_loc.apply(s)).min().unwrap()
 }
 
-fn part2(txt: &str) -> i64 {
-    let almanac: Almanac = Almanac::new(txt);
-    let seed_ranges = almanac.seed_ranges_p2();
-    seed_ranges.iter().map(|r| r.start).min().unwrap()
+fn part2(almanac: &Almanac, seed_to_loc: &PiecewiseMap) -> i64 {
+    almanac.seed_ranges().iter()
+        .flat_map(|r| seed_to_loc.apply_range(r))
+        .map(|r| r.start)
+        .min().unwrap()
+}
+
+// Part 2 backwards: visit the pieces in order of their locations, and take
+// the first location whose seeds intersect a seed range. Since images can
+// overlap, keep going until the next piece starts past the best so far.
+fn part2_inverse(almanac: &Almanac, seed_to_loc: &PiecewiseMap) -> i64 {
+    let seed_ranges = almanac.seed_ranges();
+    let mut pieces = seed_to_loc.pieces.iter().collect::<Vec<_>>();
+    pieces.sort_by_key(|p| p.source.start + p.offset);
+
+    let mut best = i64::MAX;
+    for p in pieces {
+        if p.source.start + p.offset >= best {
+            break;
+        }
+        for r in seed_ranges.iter() {
+            let start = cmp::max(p.source.start, r.start);
+            if start < cmp::min(p.source.end, r.end) {
+                best = cmp::min(best, start + p.offset);
+            }
+        }
+    }
+    best
 }
 
 fn main() {
@@ -167,7 +284,71 @@ fn main() {
     // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
     let txt = fs::read_to_string(path).unwrap();
 
+    let almanac = Almanac::new(&txt).unwrap();
+    let seed_to_loc = almanac.compose();
+
     println!("This is {}", dayX);
-    println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+    if env::args().any(|a| a == "--table") {
+        print!("{}", seed_to_loc);
+    }
+
+    let args: Vec<String> = env::args().collect();
+
+    // --map FROM TO: the composed table between two categories
+    if let Some(i) = args.iter().position(|a| a == "--map") {
+        match args.get(i+1..i+3) {
+            Some([from, to]) => match almanac.map_between(from, to) {
+                Ok(map) => print!("{}", map),
+                Err(e) => println!("--map: {} (use --preimage for reverse queries)", e),
+            },
+            _ => println!("Usage: --map FROM TO"),
+        }
+    }
+
+    // --preimage FROM TO START END: the FROM ranges that land in START..END of TO
+    if let Some(i) = args.iter().position(|a| a == "--preimage") {
+        let query = match args.get(i+1..i+5) {
+            Some([from, to, start, end]) => match (start.parse::<i64>(), end.parse::<i64>()) {
+                (Ok(start), Ok(end)) => Some((from, to, start..end)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match query {
+            Some((from, to, target)) => match almanac.preimage(from, to, &target) {
+                Ok(ranges) => println!("{} ranges mapping into {} {:?}: {:?}", from, to, target, ranges),
+                Err(e) => println!("--preimage: {}", e),
+            },
+            None => println!("Usage: --preimage FROM TO START END"),
+        }
+    }
+
+    println!("Part 1: {:?}", part1(&almanac, &seed_to_loc));
+    println!("Part 2: {:?}", part2(&almanac, &seed_to_loc));
+
+    // --inverse: part 2 again, searching locations upward
+    if args.iter().any(|a| a == "--inverse") {
+        println!("Part 2 (inverse): {:?}", part2_inverse(&almanac, &seed_to_loc));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        let almanac = Almanac::new(include_str!("test1.txt")).unwrap();
+        let seed_to_loc = almanac.compose();
+        assert_eq!(part1(&almanac, &seed_to_loc), 35);
+        assert_eq!(part2(&almanac, &seed_to_loc), 46);
+        assert_eq!(part2_inverse(&almanac, &seed_to_loc), 46);
+    }
+
+    #[test]
+    fn inverse_matches_forward() {
+        let almanac = Almanac::new(include_str!("input.txt")).unwrap();
+        let seed_to_loc = almanac.compose();
+        assert_eq!(part2_inverse(&almanac, &seed_to_loc), part2(&almanac, &seed_to_loc));
+    }
 }
@@ -1,29 +1,322 @@
 #![allow(non_snake_case)]
 
-use std::fs;
+use std::{cmp::Ordering, fmt, fs, ops::{Add, Mul, Sub}};
 
-fn part1(txt: &str) -> i64 {
+// What the race solver needs from its number type
+trait Natural: Clone + Ord + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_small(n: u32) -> Self;
+    fn checked_minus(&self, other: &Self) -> Option<Self>;
+    fn sqrt_floor(&self) -> Self;
+    fn half(&self) -> Self;
+}
+
+impl Natural for u128 {
+    fn from_small(n: u32) -> Self {
+        n as u128
+    }
+
+    fn checked_minus(&self, other: &Self) -> Option<Self> {
+        self.checked_sub(*other)
+    }
+
+    // by Newton's method
+    fn sqrt_floor(&self) -> Self {
+        let n = *self;
+        if n < 2 {
+            return n;
+        }
+        let mut x = n;
+        let mut y = x.div_ceil(2);
+        while y < x {
+            x = y;
+            y = (x + n / x) / 2;
+        }
+        x
+    }
+
+    fn half(&self) -> Self {
+        self / 2
+    }
+}
+
+// Arbitrary-precision unsigned integer, as little-endian base 2^32
+// digits with no leading zeros (so zero has no digits)
+#[derive(Clone, PartialEq, Eq)]
+struct BigUint {
+    digits: Vec<u32>,
+}
+
+impl BigUint {
+    fn from_u128(mut n: u128) -> Self {
+        let mut digits = vec![];
+        while n > 0 {
+            digits.push(n as u32);
+            n >>= 32;
+        }
+        Self { digits }
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        if self.digits.len() > 4 {
+            return None;
+        }
+        Some(self.digits.iter().rev().fold(0, |acc, &d| (acc << 32) | d as u128))
+    }
+
+    fn from_decimal(s: &str) -> Self {
+        let mut n = Self::from_u128(0);
+        for c in s.chars() {
+            n = n.mul_add_small(10, c.to_digit(10).unwrap());
+        }
+        n
+    }
+
+    fn trimmed(mut self) -> Self {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        self
+    }
+
+    // self * m + a
+    fn mul_add_small(&self, m: u32, a: u32) -> Self {
+        let mut digits = Vec::with_capacity(self.digits.len() + 1);
+        let mut carry = a as u64;
+        for &d in self.digits.iter() {
+            let x = d as u64 * m as u64 + carry;
+            digits.push(x as u32);
+            carry = x >> 32;
+        }
+        digits.push(carry as u32);
+        Self { digits }.trimmed()
+    }
+
+    // (self / d, self % d)
+    fn divmod_small(&self, d: u32) -> (Self, u32) {
+        let mut digits = vec![0; self.digits.len()];
+        let mut rem = 0u64;
+        for (i, &x) in self.digits.iter().enumerate().rev() {
+            let cur = (rem << 32) | x as u64;
+            digits[i] = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+        (Self { digits }.trimmed(), rem as u32)
+    }
+
+    fn bits(&self) -> usize {
+        match self.digits.last() {
+            Some(&top) => 32 * self.digits.len() - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn shl(&self, n: usize) -> Self {
+        let (words, bits) = (n / 32, n % 32);
+        let mut digits = vec![0; words];
+        let mut carry = 0u32;
+        for &d in self.digits.iter() {
+            digits.push((d << bits) | carry);
+            carry = if bits == 0 { 0 } else { d >> (32 - bits) };
+        }
+        digits.push(carry);
+        Self { digits }.trimmed()
+    }
+
+    fn shr(&self, n: usize) -> Self {
+        let (words, bits) = (n / 32, n % 32);
+        let src = self.digits.get(words..).unwrap_or(&[]);
+        let digits = (0..src.len()).map(|i| {
+            let hi = if bits == 0 { 0 } else { src.get(i + 1).map_or(0, |&d| d << (32 - bits)) };
+            (src[i] >> bits) | hi
+        }).collect();
+        Self { digits }.trimmed()
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.digits.len().cmp(&other.digits.len())
+            .then_with(|| self.digits.iter().rev().cmp(other.digits.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for BigUint {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let n = self.digits.len().max(other.digits.len());
+        let mut digits = Vec::with_capacity(n + 1);
+        let mut carry = 0u64;
+        for i in 0..n {
+            let x = *self.digits.get(i).unwrap_or(&0) as u64 + *other.digits.get(i).unwrap_or(&0) as u64 + carry;
+            digits.push(x as u32);
+            carry = x >> 32;
+        }
+        digits.push(carry as u32);
+        Self { digits }.trimmed()
+    }
+}
+
+impl Sub for BigUint {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_minus(&other).expect("BigUint subtraction underflow")
+    }
+}
+
+impl Mul for BigUint {
+    type Output = Self;
+
+    // schoolbook
+    fn mul(self, other: Self) -> Self {
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let x = a as u64 * b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = x as u32;
+                carry = x >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        Self { digits }.trimmed()
+    }
+}
+
+impl Natural for BigUint {
+    fn from_small(n: u32) -> Self {
+        Self::from_u128(n as u128)
+    }
+
+    fn checked_minus(&self, other: &Self) -> Option<Self> {
+        if self < other {
+            return None;
+        }
+        let mut digits = Vec::with_capacity(self.digits.len());
+        let mut borrow = 0i64;
+        for (i, &d) in self.digits.iter().enumerate() {
+            let mut x = d as i64 - *other.digits.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = (x < 0) as i64;
+            x += borrow << 32;
+            digits.push(x as u32);
+        }
+        Some(Self { digits }.trimmed())
+    }
+
+    // digit-by-digit in base 4, which only needs shifts and subtraction
+    fn sqrt_floor(&self) -> Self {
+        let mut n = self.clone();
+        let mut res = Self::from_small(0);
+        let mut bit = Self::from_small(1).shl(self.bits().saturating_sub(1) & !1);
+        while bit.bits() > 0 {
+            let trial = res.clone() + bit.clone();
+            res = res.shr(1);
+            if let Some(rest) = n.checked_minus(&trial) {
+                n = rest;
+                res = res + bit.clone();
+            }
+            bit = bit.shr(2);
+        }
+        res
+    }
+
+    fn half(&self) -> Self {
+        self.shr(1)
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(n) = self.to_u128() {
+            return write!(f, "{}", n);
+        }
+        // peel off 9 decimal digits at a time
+        let mut chunks = vec![];
+        let mut n = self.clone();
+        while n.bits() > 0 {
+            let (q, r) = n.divmod_small(1_000_000_000);
+            chunks.push(r);
+            n = q;
+        }
+        write!(f, "{}", chunks.last().unwrap())?;
+        for c in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+fn beats<N: Natural>(time: &N, dist: &N, hold: &N) -> bool {
+    hold.clone() * (time.clone() - hold.clone()) > *dist
+}
+
+// Number of hold times in 0..=time that go strictly further than dist.
+// The winners are the integers strictly between the roots of
+// h*(time - h) = dist, i.e. (time -+ sqrt(time^2 - 4 dist)) / 2.
+// The integer sqrt is exact up to flooring, so the first winner is
+// found within a step of the estimate, and the rest follow by symmetry.
+// For u128, time must be below 2^64 and dist below 2^126 so nothing overflows.
+fn ways_to_win<N: Natural>(time: N, dist: N) -> N {
+    let zero = N::from_small(0);
+    let one = N::from_small(1);
+
+    let disc = match (time.clone() * time.clone()).checked_minus(&(N::from_small(4) * dist.clone())) {
+        Some(disc) => disc,
+        None => return zero,
+    };
+    let mut lo = (time.clone() - disc.sqrt_floor()).half();
+    while lo > zero && beats(&time, &dist, &(lo.clone() - one.clone())) {
+        lo = lo - one.clone();
+    }
+    let mid = time.half();
+    while lo <= mid && !beats(&time, &dist, &lo) {
+        lo = lo + one.clone();
+    }
+    if lo > mid {
+        return zero;
+    }
+    time - N::from_small(2) * lo + one
+}
+
+// Solve with u128 when it can't overflow, otherwise with BigUint
+fn race(time: &str, dist: &str) -> BigUint {
+    match (time.parse::<u128>(), dist.parse::<u128>()) {
+        (Ok(t), Ok(d)) if t <= u64::MAX as u128 && d <= u128::MAX / 4 =>
+            BigUint::from_u128(ways_to_win(t, d)),
+        _ => ways_to_win(BigUint::from_decimal(time), BigUint::from_decimal(dist)),
+    }
+}
+
+fn part1(txt: &str) -> BigUint {
     let mut lines = txt.lines();
-    let times = lines.next().unwrap().split_whitespace().skip(1).map(|x| x.parse::<i64>().unwrap()).collect::<Vec<i64>>();
-    let dists = lines.next().unwrap().split_whitespace().skip(1).map(|x| x.parse::<i64>().unwrap()).collect::<Vec<i64>>();
+    let times = lines.next().unwrap().split_whitespace().skip(1).collect::<Vec<&str>>();
+    let dists = lines.next().unwrap().split_whitespace().skip(1).collect::<Vec<&str>>();
 
-    times.iter().zip(dists.iter()).map(|(t, d)| {
-        (0..=*t).map(|i| (t - i) * i)
-            .filter(|i| i > d)
-            .count() as i64
-        })
-        .product::<i64>()
+    times.iter().zip(dists.iter())
+        .map(|(t, d)| race(t, d))
+        .fold(BigUint::from_small(1), |acc, n| acc * n)
 }
 
-fn part2(txt: &str) -> i64 {
+fn part2(txt: &str) -> BigUint {
     let mut lines = txt.lines()
         .map(|l| l.split_whitespace().collect::<Vec<&str>>().join(""));
-    let time = lines.next().unwrap().split(":").skip(1).next().unwrap().parse::<i64>().unwrap();
-    let dist = lines.next().unwrap().split(":").skip(1).next().unwrap().parse::<i64>().unwrap();
+    let time = lines.next().unwrap().split(':').nth(1).unwrap().to_string();
+    let dist = lines.next().unwrap().split(':').nth(1).unwrap().to_string();
 
-    (0..=time).map(|i| (time - i) * i)
-        .filter(|i| *i > dist)
-        .count() as i64
+    race(&time, &dist)
 }
 
 fn main() {
@@ -35,6 +328,72 @@ fn main() {
     let txt = fs::read_to_string(path).unwrap();
 
     println!("This is {}", dayX);
+
     println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift64, so the tests need no external crates and stay reproducible
+    fn xorshift(mut state: u64) -> impl FnMut() -> u128 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u128
+        }
+    }
+
+    fn ways_to_win_brute(time: u128, dist: u128) -> u128 {
+        (0..=time).filter(|&h| beats(&time, &dist, &h)).count() as u128
+    }
+
+    // random races, concentrating on records near the maximum distance
+    #[test]
+    fn closed_form_matches_brute_force() {
+        let mut rand = xorshift(0x2545F4914F6CDD1D);
+        for _ in 0..2000 {
+            let time = rand() % 2000;
+            let best = (time / 2) * (time - time / 2);
+            let dist = match rand() % 3 {
+                0 => rand() % (best + 1),
+                1 => best - rand() % (best + 1).min(10),
+                _ => best + rand() % 3,
+            };
+            assert_eq!(ways_to_win(time, dist), ways_to_win_brute(time, dist), "time {}, dist {}", time, dist);
+        }
+    }
+
+    #[test]
+    fn sqrt_floor_at_perfect_squares() {
+        let mut rand = xorshift(0x9E3779B97F4A7C15);
+        for _ in 0..2000 {
+            let r = rand() >> 1;
+            assert_eq!((r * r).sqrt_floor(), r);
+            assert_eq!((r * r + 2 * r).sqrt_floor(), r);
+            assert_eq!((r * r + 2 * r + 1).sqrt_floor(), r + 1);
+
+            let big_r = BigUint::from_u128(r);
+            assert_eq!((big_r.clone() * big_r.clone()).sqrt_floor(), big_r);
+            assert_eq!((big_r.clone() * big_r.clone() + BigUint::from_u128(2 * r)).sqrt_floor(), big_r);
+        }
+    }
+
+    #[test]
+    fn biguint_matches_u128() {
+        let mut rand = xorshift(0xD1B54A32D192ED03);
+        for _ in 0..2000 {
+            let time = rand();
+            let dist = rand() * rand() % ((time / 2) * (time - time / 2) + 1);
+            assert_eq!(
+                ways_to_win(BigUint::from_u128(time), BigUint::from_u128(dist)),
+                BigUint::from_u128(ways_to_win(time, dist)),
+                "time {}, dist {}", time, dist
+            );
+            assert_eq!(BigUint::from_decimal(&dist.to_string()).to_string(), dist.to_string());
+        }
+    }
+}
@@ -1,51 +1,166 @@
 #![allow(non_snake_case)]
 
-use std::{fs, cmp::Ordering};
+use std::{env, fs, cmp::{Ordering, Reverse}};
 
-// #[derive(Debug, Clone, Copy)]
-// enum Card {
-//     NUM(i64),
-//     T,
-//     J,
-//     K,
-//     Q,
-//     A,
-// }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Card {
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+}
+
+const ALL_CARDS: [Card; 13] = [
+    Card::Two, Card::Three, Card::Four, Card::Five, Card::Six, Card::Seven, Card::Eight,
+    Card::Nine, Card::Ten, Card::Jack, Card::Queen, Card::King, Card::Ace,
+];
+
+impl Card {
+    fn from_char(c: char) -> Card {
+        match c {
+            '2' => Card::Two,
+            '3' => Card::Three,
+            '4' => Card::Four,
+            '5' => Card::Five,
+            '6' => Card::Six,
+            '7' => Card::Seven,
+            '8' => Card::Eight,
+            '9' => Card::Nine,
+            'T' => Card::Ten,
+            'J' => Card::Jack,
+            'Q' => Card::Queen,
+            'K' => Card::King,
+            'A' => Card::Ace,
+            _ => panic!("Unknown card {}", c),
+        }
+    }
+}
 
-type Card = i64;
+// A hand's type is its pattern of rank multiplicities, largest first,
+// e.g. [3, 2] for a full house. Comparing patterns lexicographically
+// gives the usual order for five cards, and extends it to any hand size.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct HandType {
+    counts: Vec<usize>,
+}
+
+impl HandType {
+    fn name(&self) -> String {
+        match self.counts.as_slice() {
+            [5] => String::from("Five of a kind"),
+            [4, 1] => String::from("Four of a kind"),
+            [3, 2] => String::from("Full house"),
+            [3, 1, 1] => String::from("Three of a kind"),
+            [2, 2, 1] => String::from("Two pair"),
+            [2, 1, 1, 1] => String::from("One pair"),
+            [1, 1, 1, 1, 1] => String::from("High card"),
+            counts => counts.iter().map(|c| c.to_string()).collect::<Vec<_>>().join("+"),
+        }
+    }
+}
 
-fn card_from_char(c: char, p2: bool) -> Card {
-    match c {
-        'T' => 10,
-        'J' => if p2 { 1 } else { 11 },
-        'Q' => 12,
-        'K' => 13,
-        'A' => 14,
-        _ => c.to_digit(10).unwrap() as i64,
+// The best pattern reachable by adding nwild cards to the groups in counts.
+// Patterns compare lexicographically, so every wild joins the largest group.
+fn best_counts(counts: &[usize], nwild: usize) -> Vec<usize> {
+    let mut sorted = counts.to_vec();
+    sorted.sort_by(|a, b| b.cmp(a));
+    match sorted.first_mut() {
+        Some(largest) => *largest += nwild,
+        None if nwild > 0 => sorted.push(nwild),
+        None => {}
     }
+    sorted
 }
 
-type HandType = i64;
+// Which end of the hand breaks ties between hands of the same type
+#[derive(Debug, Clone, Copy)]
+enum TieBreak {
+    LeftToRight,
+    RightToLeft,
+}
 
-#[derive(Debug, Clone, Copy, Eq)]
+// Everything that differs between variants of the game
+#[derive(Debug, Clone)]
+struct Rules {
+    wild: Vec<Card>,  // cards that stand in for whatever makes the best hand
+    order: Vec<Card>,  // weakest to strongest, for breaking ties
+    tie_break: TieBreak,
+}
+
+impl Rules {
+    fn part1() -> Self {
+        Self { wild: vec![], order: ALL_CARDS.to_vec(), tie_break: TieBreak::LeftToRight }
+    }
+
+    // jacks are jokers: wild, and the weakest card
+    fn part2() -> Self {
+        let mut order = vec![Card::Jack];
+        order.extend(ALL_CARDS.iter().filter(|&&c| c != Card::Jack));
+        Self { wild: vec![Card::Jack], order, tie_break: TieBreak::LeftToRight }
+    }
+
+    // --wild CARDS --order CARDS --tie-break ltr|rtl, on top of the part 1 rules
+    fn from_args(args: &[String]) -> Option<Self> {
+        let mut rules = Self::part1();
+        let mut custom = false;
+        for w in args.windows(2) {
+            match w[0].as_str() {
+                "--wild" => rules.wild = w[1].chars().map(Card::from_char).collect(),
+                "--order" => rules.order = w[1].chars().map(Card::from_char).collect(),
+                "--tie-break" => rules.tie_break = match w[1].as_str() {
+                    "ltr" => TieBreak::LeftToRight,
+                    "rtl" => TieBreak::RightToLeft,
+                    s => panic!("Unknown tie-break {}", s),
+                },
+                _ => continue,
+            }
+            custom = true;
+        }
+        assert!(rules.order.len() == ALL_CARDS.len(), "--order must list all 13 cards");
+        custom.then_some(rules)
+    }
+
+    fn strength(&self, card: Card) -> usize {
+        self.order.iter().position(|&c| c == card).unwrap()
+    }
+
+    fn hand_type(&self, cards: &[Card]) -> HandType {
+        let mut occurences = [0; 13];
+        let mut nwild = 0;
+        for &c in cards {
+            if self.wild.contains(&c) {
+                nwild += 1;
+            } else {
+                occurences[c as usize] += 1;
+            }
+        }
+
+        let counts = occurences.iter().copied().filter(|&n| n > 0).collect::<Vec<usize>>();
+        HandType { counts: best_counts(&counts, nwild) }
+    }
+}
+
+#[derive(Debug, Clone, Eq)]
 struct Hand {
-    cards: [Card; 5],
+    text: String,  // the cards as written
     bid: i64,
-    // occurences: [i64; 15],
     hand_type: HandType,
+    strengths: Vec<usize>,  // card strengths, in tie-break order
 }
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
         self.hand_type.cmp(&other.hand_type)
-            .then_with(|| {
-                for i in 0..5 {
-                    if self.cards[i] != other.cards[i] {
-                        return self.cards[i].cmp(&other.cards[i]);
-                    }
-                }
-                Ordering::Equal
-            })
+            .then_with(|| self.strengths.cmp(&other.strengths))
     }
 }
 
@@ -62,81 +177,125 @@ impl PartialEq for Hand {
 }
 
 impl Hand {
-    fn from_str(s: &str, p2: bool) -> Hand {
-        let mut cards = [0; 5];
-        let mut occurences = [0; 15];
-        let mut i = 0;
+    fn from_str(s: &str, rules: &Rules) -> Hand {
         let mut ss = s.split_whitespace();
-        for c in ss.next().unwrap().chars() {
-            cards[i] = card_from_char(c, p2);
-            occurences[cards[i] as usize] += 1;
-            i += 1;
-        }
+        let text = ss.next().unwrap();
+        let cards = text.chars().map(Card::from_char).collect::<Vec<Card>>();
 
-        let hand_type = {
-            let mut sorted = occurences.clone();
-            sorted.sort();
-            sorted.reverse();
-
-            let NJ = if p2 { occurences[1] } else { 0 };
-            if sorted[0] == 5 {
-                7  // five of a kind
-            } else if sorted[0] == 4 {
-                if NJ == 1 || NJ == 4 { 7 } else { 6 }   // four of a kind
-            } else if sorted[0] == 3 && sorted[1] == 2 {
-                if NJ == 3 || NJ == 2 { 7 } else { 5 } // full house
-            } else if sorted[0] == 3 {
-                if NJ == 1 || NJ == 3 { 6 } else { 4 } // three of a kind
-            } else if sorted[0] == 2 && sorted[1] == 2 {
-                if NJ == 1 { 5 } else if NJ == 2 { 6 } else { 3 } // two pairs
-            } else if sorted[0] == 2 {
-                if NJ == 2 || NJ == 1 { 4 } else { 2 } // one pair
-            } else {
-                if NJ == 1 { 2 } else { 1 } // high card
-            }
-        };
+        let mut strengths = cards.iter().map(|&c| rules.strength(c)).collect::<Vec<_>>();
+        if let TieBreak::RightToLeft = rules.tie_break {
+            strengths.reverse();
+        }
 
         Hand {
-            cards,
+            hand_type: rules.hand_type(&cards),
+            text: text.to_string(),
             bid: ss.next().unwrap().parse::<i64>().unwrap(),
-            hand_type,
+            strengths,
         }
     }
+}
 
-
+#[derive(Debug, Clone)]
+struct Ranked {
+    hand: Hand,
+    rank: usize,  // 1 is the weakest
+    winnings: i64,
 }
 
-fn part1(txt: &str) -> i64 {
-    let mut hands = txt
+// Hands in input order, with their ranks under these rules
+fn rank_hands(txt: &str, rules: &Rules) -> Vec<Ranked> {
+    let hands = txt
         .lines()
-        .map(|s| Hand::from_str(s, false))
+        .map(|s| Hand::from_str(s, rules))
         .collect::<Vec<Hand>>();
-    // println!("{:?}", hands);
-    hands.sort();
-    // println!("{:?}", hands);
 
-    hands.iter()
-        .enumerate()
-        .map(|(i, h)| h.bid * (i as i64 + 1))
-        .sum()
+    let mut order = (0..hands.len()).collect::<Vec<usize>>();
+    order.sort_by(|&i, &j| hands[i].cmp(&hands[j]));
+    let mut ranks = vec![0; hands.len()];
+    for (r, &i) in order.iter().enumerate() {
+        ranks[i] = r + 1;
+    }
+
+    hands.into_iter().zip(ranks).map(|(hand, rank)| Ranked {
+        winnings: hand.bid * rank as i64,
+        hand,
+        rank,
+    }).collect()
 }
 
-fn part2(txt: &str) -> i64 {
-    let mut hands = txt
-        .lines()
-        .map(|s| Hand::from_str(s, true))
-        .collect::<Vec<Hand>>();
-    // println!("{:?}", hands);
-    for h in hands.iter() {
-        println!("{:?}", h);
+fn winnings(txt: &str, rules: &Rules) -> i64 {
+    rank_hands(txt, rules).iter().map(|r| r.winnings).sum()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Hand,
+    Type,
+    Rank,
+    Bid,
+    Winnings,
+}
+
+impl Column {
+    fn from_str(s: &str) -> Column {
+        match s {
+            "hand" => Column::Hand,
+            "type" => Column::Type,
+            "rank" => Column::Rank,
+            "bid" => Column::Bid,
+            "winnings" => Column::Winnings,
+            _ => panic!("Unknown column {}", s),
+        }
+    }
+
+    fn cmp(&self, a: &Ranked, b: &Ranked) -> Ordering {
+        match self {
+            Column::Hand => a.hand.text.cmp(&b.hand.text),
+            Column::Type => a.hand.hand_type.cmp(&b.hand.hand_type),
+            Column::Rank => a.rank.cmp(&b.rank),
+            Column::Bid => a.hand.bid.cmp(&b.hand.bid),
+            Column::Winnings => a.winnings.cmp(&b.winnings),
+        }
+    }
+}
+
+fn report(txt: &str, rules: &Rules, sort: Column, desc: bool) {
+    let mut rows = rank_hands(txt, rules);
+    // ties keep input order
+    rows.sort_by(|a, b| if desc { sort.cmp(b, a) } else { sort.cmp(a, b) });
+
+    println!("{:<8} {:<16} {:>6} {:>6} {:>10}", "hand", "type", "rank", "bid", "winnings");
+    for r in rows.iter() {
+        println!("{:<8} {:<16} {:>6} {:>6} {:>10}",
+            r.hand.text, r.hand.hand_type.name(), r.rank, r.hand.bid, r.winnings);
     }
-    hands.sort();
-    println!("{:?}", hands);
+    println!("Total winnings: {}", rows.iter().map(|r| r.winnings).sum::<i64>());
+}
+
+// How each hand moves between the part 1 and part 2 rules,
+// biggest moves first
+fn rank_diff(txt: &str) {
+    let before = rank_hands(txt, &Rules::part1());
+    let after = rank_hands(txt, &Rules::part2());
 
-    hands.iter()
-        .enumerate()
-        .map(|(i, h)| h.bid * (i as i64 + 1))
-        .sum()
+    let mut rows = before.iter().zip(after.iter()).collect::<Vec<_>>();
+    rows.sort_by_key(|(b, a)| Reverse((a.rank as i64 - b.rank as i64).abs()));
+
+    println!("{:<8} {:<16} {:>6}    {:<16} {:>6} {:>7}", "hand", "part 1 type", "rank", "part 2 type", "rank", "change");
+    for (b, a) in rows {
+        println!("{:<8} {:<16} {:>6} -> {:<16} {:>6} {:>+7}",
+            b.hand.text, b.hand.hand_type.name(), b.rank,
+            a.hand.hand_type.name(), a.rank, a.rank as i64 - b.rank as i64);
+    }
+}
+
+fn part1(txt: &str) -> i64 {
+    winnings(txt, &Rules::part1())
+}
+
+fn part2(txt: &str) -> i64 {
+    winnings(txt, &Rules::part2())
 }
 
 fn main() {
@@ -150,4 +309,92 @@ fn main() {
     println!("This is {}", dayX);
     println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));
+
+    let args: Vec<String> = env::args().collect();
+    let custom = Rules::from_args(&args);
+    if let Some(rules) = &custom {
+        println!("Custom rules: {:?}", winnings(&txt, rules));
+    }
+
+    // --report 1|2|custom [--sort hand|type|rank|bid|winnings] [--desc]
+    if let Some(i) = args.iter().position(|a| a == "--report") {
+        let rules = match args.get(i+1).map(String::as_str) {
+            Some("1") => Some(Rules::part1()),
+            Some("2") => Some(Rules::part2()),
+            Some("custom") => custom,
+            _ => None,
+        };
+        let sort = match args.iter().position(|a| a == "--sort") {
+            Some(j) => args.get(j+1).map(|s| Column::from_str(s)),
+            None => Some(Column::Rank),
+        };
+        match (rules, sort) {
+            (Some(rules), Some(sort)) => report(&txt, &rules, sort, args.iter().any(|a| a == "--desc")),
+            _ => println!("Usage: --report 1|2|custom [--sort hand|type|rank|bid|winnings] [--desc]; \
+                custom needs --wild, --order or --tie-break"),
+        }
+    }
+
+    // --diff: rank changes from part 1 to part 2
+    if args.iter().any(|a| a == "--diff") {
+        rank_diff(&txt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The original decision table for five cards, keyed on the two largest
+    // rank counts and the number of jokers, with types numbered 1..=7
+    fn table_hand_type(cards: &[Card], p2: bool) -> i64 {
+        let mut occurences = [0; 13];
+        for &c in cards {
+            occurences[c as usize] += 1;
+        }
+        let NJ = if p2 { occurences[Card::Jack as usize] } else { 0 };
+        let mut sorted = occurences;
+        sorted.sort();
+        sorted.reverse();
+
+        if sorted[0] == 5 {
+            7  // five of a kind
+        } else if sorted[0] == 4 {
+            if NJ == 1 || NJ == 4 { 7 } else { 6 }   // four of a kind
+        } else if sorted[0] == 3 && sorted[1] == 2 {
+            if NJ == 3 || NJ == 2 { 7 } else { 5 } // full house
+        } else if sorted[0] == 3 {
+            if NJ == 1 || NJ == 3 { 6 } else { 4 } // three of a kind
+        } else if sorted[0] == 2 && sorted[1] == 2 {
+            if NJ == 1 { 5 } else if NJ == 2 { 6 } else { 3 } // two pairs
+        } else if sorted[0] == 2 {
+            if NJ == 2 || NJ == 1 { 4 } else { 2 } // one pair
+        } else if NJ == 1 { 2 } else { 1 } // high card
+    }
+
+    // The evaluator agrees with the original table on every five-card hand,
+    // with and without jokers
+    #[test]
+    fn matches_five_card_table() {
+        let names = ["High card", "One pair", "Two pair", "Three of a kind",
+            "Full house", "Four of a kind", "Five of a kind"];
+        for (p2, rules) in [(false, Rules::part1()), (true, Rules::part2())] {
+            for mut i in 0..ALL_CARDS.len().pow(5) {
+                let mut cards = vec![];
+                for _ in 0..5 {
+                    cards.push(ALL_CARDS[i % 13]);
+                    i /= 13;
+                }
+                let expected = names[table_hand_type(&cards, p2) as usize - 1];
+                assert_eq!(rules.hand_type(&cards).name(), expected, "{:?}, jokers: {}", cards, p2);
+            }
+        }
+    }
+
+    #[test]
+    fn many_wilds() {
+        assert_eq!(best_counts(&[3, 2, 1], 16), vec![19, 2, 1]);
+        assert_eq!(best_counts(&[], 20), vec![20]);
+        assert_eq!(best_counts(&[], 0), Vec::<usize>::new());
+    }
 }
@@ -1,96 +1,193 @@
 #![allow(non_snake_case)]
 
-use std::{fs, collections::{HashMap, HashSet}, str::Lines};
+use std::{fs, collections::HashMap, str::Lines};
 
-struct Node {
-    left: String,
-    right: String,
+type NodeId = usize;
+
+// The L/R instructions as one bit per step, set for R
+struct Instructions {
+    bits: Vec<u64>,
+    len: usize,
 }
 
+impl Instructions {
+    fn from_str(s: &str) -> Instructions {
+        let mut bits = vec![0; s.len().div_ceil(64)];
+        for (i, c) in s.chars().enumerate() {
+            match c {
+                'L' => (),
+                'R' => bits[i / 64] |= 1 << (i % 64),
+                _ => panic!("Unknown step {}", c),
+            }
+        }
+        Instructions { bits, len: s.len() }
+    }
+
+    fn right(&self, i: usize) -> bool {
+        (self.bits[i / 64] >> (i % 64)) & 1 == 1
+    }
+}
+
+// Node names are interned to dense ids in order of first appearance,
+// so walking the network is just indexing into left/right
 struct Network {
-    nodes: HashMap<String,Node>,
+    names: Vec<String>,
+    ids: HashMap<String, NodeId>,
+    left: Vec<NodeId>,
+    right: Vec<NodeId>,
+    is_z: Vec<bool>,
 }
 
 impl Network {
     fn from_txt(lines: Lines) -> Network {
-        let mut nodes = HashMap::new();
+        let mut network = Network {
+            names: vec![],
+            ids: HashMap::new(),
+            left: vec![],
+            right: vec![],
+            is_z: vec![],
+        };
+        let mut children: Vec<Option<(NodeId, NodeId)>> = vec![];
 
         for line in lines {
             let mut tokens = line.split([' ', '=', '(' , ')', ','])
-                .filter(|&s| s.len() > 0);
-            nodes.insert(
-                tokens.next().unwrap().to_owned(),
-                Node {
-                    left: tokens.next().unwrap().to_owned(),
-                    right: tokens.next().unwrap().to_owned(),
-                });
+                .filter(|&s| !s.is_empty());
+            let node = network.intern(tokens.next().unwrap());
+            let left = network.intern(tokens.next().unwrap());
+            let right = network.intern(tokens.next().unwrap());
+            children.resize(network.names.len(), None);
+            children[node] = Some((left, right));
+        }
+
+        for (id, c) in children.iter().enumerate() {
+            let (left, right) = c.unwrap_or_else(|| panic!("Node {} is never defined", network.names[id]));
+            network.left.push(left);
+            network.right.push(right);
         }
+        network.is_z = network.names.iter().map(|n| n.ends_with('Z')).collect();
 
-        Network{nodes}
+        network
     }
 
-    fn get(&self, name: &str) -> &Node {
-        self.nodes.get(name).unwrap()
+    fn intern(&mut self, name: &str) -> NodeId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), self.names.len() - 1);
+        self.names.len() - 1
     }
 
-    fn find_len(&self, steps: &Vec<char>, target: &str) -> i64 {
-        let mut cur = "AAA";
-        let mut i = 0;
-        for s in steps.iter().cycle() {
-            if cur == target {
-                return i;
+    fn id(&self, name: &str) -> NodeId {
+        *self.ids.get(name).unwrap()
+    }
+
+    fn step(&self, node: NodeId, right: bool) -> NodeId {
+        if right { self.right[node] } else { self.left[node] }
+    }
+
+    // Graphviz, with edges labelled L and R; starting nodes (..A) are
+    // green and Z nodes are red
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n");
+        for (id, name) in self.names.iter().enumerate() {
+            if name.ends_with('A') {
+                dot += &format!("    \"{}\" [style=filled, fillcolor=palegreen];\n", name);
+            } else if self.is_z[id] {
+                dot += &format!("    \"{}\" [shape=doublecircle, style=filled, fillcolor=salmon];\n", name);
             }
-            match s {
-                'L' => cur = &self.get(cur).left,
-                'R' => cur = &self.get(cur).right,
-                _ => panic!("Unknown step {}", s),
+        }
+        for (id, name) in self.names.iter().enumerate() {
+            if self.left[id] == self.right[id] {
+                dot += &format!("    \"{}\" -> \"{}\" [label=\"L,R\"];\n", name, self.names[self.left[id]]);
+            } else {
+                dot += &format!("    \"{}\" -> \"{}\" [label=L];\n", name, self.names[self.left[id]]);
+                dot += &format!("    \"{}\" -> \"{}\" [label=R];\n", name, self.names[self.right[id]]);
             }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    #[allow(dead_code)]
+    fn find_len(&self, steps: &Instructions, target: &str) -> i64 {
+        let mut cur = self.id("AAA");
+        let target = self.id(target);
+        let mut i = 0;
+        while cur != target {
+            cur = self.step(cur, steps.right(i % steps.len));
             i += 1;
         }
-        i
+        i as i64
     }
 
-    fn nodes_ending_with(&self, X: &str) -> Vec<&str> {
-        self.nodes.keys()
-            .filter(|&k| k.ends_with(X))
-            .map(|s| s.as_str())
+    fn nodes_ending_with(&self, X: &str) -> Vec<NodeId> {
+        (0..self.names.len())
+            .filter(|&id| self.names[id].ends_with(X))
             .collect()
     }
 
-    fn find_zs(&self, from: &str, steps: &Vec<char>) -> Vec<i64> {
+    // Walk from a node until a (node, instruction index) state repeats.
+    // States are numbered node * steps.len + index, so `seen` is a flat table.
+    fn walk(&self, from: NodeId, steps: &Instructions) -> Walk {
         let mut cur = from;
-        let mut i: i64 = 0;
-        let mut seen_states: HashSet<(&str,i64)> = HashSet::new();
-        let mut zdists: Vec<i64> = Vec::new();
-        for s in steps.iter().cycle() {
-            let state = (cur, i % steps.len() as i64);
-            if seen_states.contains(&state) {
-                break;
+        let mut seen: Vec<i64> = vec![-1; self.names.len() * steps.len];
+        let mut hits: Vec<(i64, NodeId)> = Vec::new();
+        for i in 0.. {
+            let k = i as usize % steps.len;
+            let state = cur * steps.len + k;
+            if seen[state] >= 0 {
+                let first = seen[state];
+                return Walk {
+                    tail: first,
+                    cycle: i - first,
+                    tail_zs: hits.iter().map(|h| h.0).filter(|&z| z < first).collect(),
+                    cycle_zs: hits.iter().map(|h| h.0).filter(|&z| z >= first).collect(),
+                    hits,
+                };
             }
-            seen_states.insert(state);
-            if cur.ends_with("Z") {
-                zdists.push(i);
+            seen[state] = i;
+            if self.is_z[cur] {
+                hits.push((i, cur));
             }
-            match s {
-                'L' => cur = &self.get(cur).left,
-                'R' => cur = &self.get(cur).right,
-                _ => panic!("Unknown step {}", s),
-            }
-            i += 1;
+            cur = self.step(cur, steps.right(k));
+        }
+        unreachable!()
+    }
+}
+
+// A ghost's path: `tail` steps to reach its cycle, which then repeats every
+// `cycle` steps. It's on a Z node at each step in tail_zs, and at
+// z + k*cycle for each z in cycle_zs and k >= 0.
+#[derive(Debug, Clone)]
+struct Walk {
+    tail: i64,
+    cycle: i64,
+    tail_zs: Vec<i64>,
+    cycle_zs: Vec<i64>,
+    hits: Vec<(i64, NodeId)>,  // every Z visit (step, node) up to the first repeat
+}
+
+impl Walk {
+    fn on_z(&self, t: i64) -> bool {
+        if t < self.tail {
+            self.tail_zs.contains(&t)
+        } else {
+            self.cycle_zs.iter().any(|&z| (t - z).rem_euclid(self.cycle) == 0)
         }
-        zdists
     }
 }
 
+#[allow(dead_code)]
 fn part1(txt: &str) -> i64 {
     let mut lines = txt.lines();
-    let steps = lines.next().unwrap().chars().collect();
+    let steps = Instructions::from_str(lines.next().unwrap());
     lines.next();
     let network = Network::from_txt(lines);
     network.find_len(&steps, "ZZZ")
 }
 
-fn gcd(a: i64, b: i64) -> i64 {
+fn gcd(a: i128, b: i128) -> i128 {
     let mut a = a;
     let mut b = b;
     while b != 0 {
@@ -101,52 +198,106 @@ fn gcd(a: i64, b: i64) -> i64 {
     a.abs()
 }
 
-fn lcm(nums: Vec<i64>) -> i64 {
-    let mut cur = nums[0];
-    for n in nums.iter().skip(1) {
-        cur = cur * n / gcd(cur, *n);
+fn lcm(nums: &[i128]) -> i128 {
+    nums.iter().fold(1, |cur, &n| cur * n / gcd(cur, n))
+}
+
+// (g, x, y) with a*x + b*y = g = gcd(a, b)
+fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = ext_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
-    cur
 }
 
-fn part2(txt: &str) -> i64 {
-    // For each A node, find the number of steps to all Z nodes
-    // Then find the smallest shared value in those sets
+// Generalized CRT: combine t = a1 mod m1 and t = a2 mod m2, where the
+// moduli need not be coprime. None if the two can't both hold.
+fn crt(a1: i128, m1: i128, a2: i128, m2: i128) -> Option<(i128, i128)> {
+    let (g, x, _) = ext_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let l = m1 / g * m2;
+    let k = ((a2 - a1) / g * x).rem_euclid(m2 / g);
+    Some(((a1 + m1 * k).rem_euclid(l), l))
+}
+
+// Earliest step where every ghost is on a Z node at once
+fn simultaneous(walks: &[Walk]) -> Option<i64> {
+    // Before every ghost is in its cycle, just check each step
+    let start = walks.iter().map(|w| w.tail).max().unwrap();
+    if let Some(t) = (0..start).find(|&t| walks.iter().all(|w| w.on_z(t))) {
+        return Some(t);
+    }
+
+    // After that, each ghost allows a set of residues mod its cycle length.
+    // Fold them together one ghost at a time, keeping every consistent residue.
+    let mut residues: Vec<(i128, i128)> = vec![(0, 1)];
+    for w in walks {
+        let mut next = residues.iter()
+            .flat_map(|&(a, m)| w.cycle_zs.iter().filter_map(move |&z| crt(a, m, z as i128, w.cycle as i128)))
+            .collect::<Vec<_>>();
+        next.sort();
+        next.dedup();
+        residues = next;
+    }
+
+    // smallest t >= start in any of the residue classes
+    residues.iter()
+        .map(|&(a, m)| a + (start as i128 - a + m - 1).div_euclid(m).max(0) * m)
+        .min()
+        .map(|t| t as i64)
+}
+
+// What each ghost's path looks like, and whether the LCM of the cycle
+// lengths would give the right answer. That shortcut assumes each ghost
+// sees no Z before its cycle and exactly one Z per cycle, at an offset
+// that's a multiple of the cycle length (so it's at Z on every k*cycle).
+fn cycle_report(txt: &str) {
     let mut lines = txt.lines();
-    let steps: Vec<char> = lines.next().unwrap().chars().collect();
+    let steps = Instructions::from_str(lines.next().unwrap());
     lines.next();
     let network = Network::from_txt(lines);
 
-    // let mut lens: Vec<Vec<i64>> = Vec::new();
-    let mut lens: Vec<Vec<i64>> = network.nodes_ending_with("A").iter()
-        .map(|&s| network.find_zs(s, &steps))
-        .collect();
-    
-    // LCM of the cycle lens
-    let f = lcm(
-        lens.iter()
-            .map(|v| *v.last().unwrap() as i64)
-            .collect()
-        );
-    
-    for l in lens.iter_mut() {
-        let cyclen = *l.last().unwrap();
-        for v in l.iter_mut() {
-            *v *= f / cyclen;
-        }
+    let mut walks = vec![];
+    let mut lcm_ok = true;
+    for start in network.nodes_ending_with("A") {
+        let w = network.walk(start, &steps);
+        let hits = w.hits.iter()
+            .map(|&(z, node)| format!("{}@{}", network.names[node], z))
+            .collect::<Vec<_>>();
+        let ok = w.tail_zs.is_empty() && w.cycle_zs.len() == 1 && w.cycle_zs[0] % w.cycle == 0;
+        lcm_ok &= ok;
+
+        // a cycle repeats a (node, instruction index) state, so it's a
+        // whole number of passes through the instructions
+        println!("{}: tail {}, cycle {} ({} x {} instructions), Z hits [{}], cycle offsets {:?}, LCM-friendly: {}",
+            network.names[start], w.tail, w.cycle, w.cycle / steps.len as i64, steps.len,
+            hits.join(", "),
+            w.cycle_zs.iter().map(|z| z % w.cycle).collect::<Vec<_>>(),
+            ok);
+        walks.push(w);
     }
 
-    let sets = lens.iter()
-        .map(|v| v.iter().cloned().collect::<HashSet<i64>>())
-        .collect::<Vec<HashSet<i64>>>();
+    let shortcut = lcm(&walks.iter().map(|w| w.cycle as i128).collect::<Vec<_>>());
+    println!("LCM of cycle lengths: {}", shortcut);
+    println!("General solution: {:?}", simultaneous(&walks));
+    println!("Input {} the LCM shortcut's assumptions", if lcm_ok { "satisfies" } else { "violates" });
+}
 
-    // smallest common value in sets
-    let mut state = sets.get(0).unwrap().clone();
-    for v in sets.iter().skip(1) {
-        state = state.intersection(v).cloned().collect::<HashSet<i64>>();
-    }
+fn part2(txt: &str) -> i64 {
+    let mut lines = txt.lines();
+    let steps = Instructions::from_str(lines.next().unwrap());
+    lines.next();
+    let network = Network::from_txt(lines);
+
+    let walks: Vec<Walk> = network.nodes_ending_with("A").iter()
+        .map(|&id| network.walk(id, &steps))
+        .collect();
 
-    *state.iter().min().unwrap()
+    simultaneous(&walks).expect("The ghosts never line up")
 }
 
 fn main() {
@@ -159,7 +310,86 @@ fn main() {
     // let path = String::from(root) + "/src/" + dayX + "/test3.txt";
     let txt = fs::read_to_string(path).unwrap();
 
+    // --dot FILE writes the network for Graphviz
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--dot") {
+        match args.get(i+1) {
+            Some(file) => {
+                let mut lines = txt.lines();
+                lines.next();
+                lines.next();
+                fs::write(file, Network::from_txt(lines).to_dot()).unwrap();
+            }
+            None => println!("Usage: --dot FILE"),
+        }
+    }
+
     println!("This is {}", dayX);
     // println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));
+
+    if args.iter().any(|a| a == "--report") {
+        cycle_report(&txt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // step every ghost at once until they're all on a Z node
+    fn brute_force(txt: &str) -> i64 {
+        let mut lines = txt.lines();
+        let steps = Instructions::from_str(lines.next().unwrap());
+        lines.next();
+        let network = Network::from_txt(lines);
+
+        let mut ghosts = network.nodes_ending_with("A");
+        for t in 0..100_000 {
+            if ghosts.iter().all(|&g| network.is_z[g]) {
+                return t;
+            }
+            let right = steps.right(t as usize % steps.len);
+            ghosts = ghosts.iter().map(|&g| network.step(g, right)).collect();
+        }
+        panic!("The ghosts never line up");
+    }
+
+    #[test]
+    fn example() {
+        let txt = include_str!("test3.txt");
+        assert_eq!(part2(txt), 6);
+        assert_eq!(brute_force(txt), 6);
+    }
+
+    // GAA has a two-step tail and two Z nodes in its cycle of 5;
+    // HAA starts with a Z in its tail before a cycle of 3
+    #[test]
+    fn tails_and_several_zs_per_cycle() {
+        let txt = "L
+
+GAA = (GT1, GT1)
+GT1 = (GC1, GC1)
+GC1 = (G1Z, G1Z)
+G1Z = (GC2, GC2)
+GC2 = (G2Z, G2Z)
+G2Z = (GC3, GC3)
+GC3 = (GC1, GC1)
+HAA = (H1Z, H1Z)
+H1Z = (HC1, HC1)
+HC1 = (HC2, HC2)
+HC2 = (H2Z, H2Z)
+H2Z = (HC1, HC1)";
+        let mut lines = txt.lines();
+        let steps = Instructions::from_str(lines.next().unwrap());
+        lines.next();
+        let network = Network::from_txt(lines);
+        let g = network.walk(network.id("GAA"), &steps);
+        assert_eq!((g.tail, g.cycle, g.cycle_zs.clone()), (2, 5, vec![3, 5]));
+        let h = network.walk(network.id("HAA"), &steps);
+        assert_eq!((h.tail, h.cycle, h.tail_zs.clone()), (2, 3, vec![1]));
+
+        assert_eq!(brute_force(txt), 10);
+        assert_eq!(part2(txt), 10);
+    }
 }
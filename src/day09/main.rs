@@ -1,41 +1,433 @@
 #![allow(non_snake_case)]
 
-use std::fs;
+use std::{env, fmt, fs::File, io::{self, BufRead, BufReader}};
 
-fn extrapolate(vals: &Vec<i64>) -> i64 {
-    if vals.iter().all(|&x| x == vals[0]) {
-        vals[0]
-    } else {
-        extrapolate(
-            &vals.iter().skip(1).zip(vals.iter())
-                .map(|(x,y)| x-y).collect(),
-        ) + vals.last().unwrap()
+// What the extrapolation engine needs from its numbers;
+// the checked ops return None on overflow
+trait Num: Copy + PartialEq + fmt::Display {
+    fn from_int(n: i128) -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+// Numbers that also divide exactly, as Lagrange interpolation needs
+trait Field: Num {
+    fn checked_div(self, other: Self) -> Option<Self>;
+}
+
+impl Num for i128 {
+    fn from_int(n: i128) -> Self {
+        n
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        i128::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        i128::checked_sub(self, other)
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        i128::checked_mul(self, other)
     }
 }
 
-fn part1(txt: &str) -> i64 {
-    txt.lines()
-        .map(|l| l.split_whitespace().map(|s| s.parse::<i64>().unwrap()).collect())
-        .map(|v: Vec<i64>| extrapolate(&v))
-        .sum()
+fn gcd(a: i128, b: i128) -> i128 {
+    let mut a = a;
+    let mut b = b;
+    while b != 0 {
+        let t = b;
+        b = a % b;
+        a = t;
+    }
+    a.abs()
 }
 
-fn part2(txt: &str) -> i64 {
-    txt.lines()
-        .map(|l| l.split_whitespace().map(|s| s.parse::<i64>().unwrap()).collect())
-        .map(|v: Vec<i64>| extrapolate(&v.iter().rev().cloned().collect::<Vec<_>>()))
-        .sum()
+// An exact fraction, always reduced with a positive denominator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    fn new(num: i128, den: i128) -> Option<Rational> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den).max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Some(Rational { num: sign.checked_mul(num / g)?, den: sign.checked_mul(den / g)? })
+    }
+
+    // "3", "-7/2" or "1.25"
+    fn from_str(s: &str) -> Result<Rational, String> {
+        let bad = || format!("can't read {:?} as a number", s);
+        let int = |t: &str| t.parse::<i128>().map_err(|_| bad());
+        if let Some((n, d)) = s.split_once('/') {
+            Rational::new(int(n)?, int(d)?).ok_or_else(bad)
+        } else if let Some((whole, frac)) = s.split_once('.') {
+            if !frac.chars().all(|c| c.is_ascii_digit()) {
+                return Err(bad());
+            }
+            let den = 10i128.checked_pow(frac.len() as u32).ok_or_else(bad)?;
+            let whole_mag = match whole.trim_start_matches('-') {
+                "" => 0,
+                w => int(w)?,
+            };
+            let frac_val = if frac.is_empty() { 0 } else { int(frac)? };
+            let mag = whole_mag.checked_mul(den)
+                .and_then(|m| m.checked_add(frac_val))
+                .ok_or_else(bad)?;
+            Rational::new(if whole.starts_with('-') { -mag } else { mag }, den).ok_or_else(bad)
+        } else {
+            Ok(Rational::from_int(int(s)?))
+        }
+    }
+}
+
+impl Num for Rational {
+    fn from_int(n: i128) -> Self {
+        Rational { num: n, den: 1 }
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        let g = gcd(self.den, other.den);
+        let den = (self.den / g).checked_mul(other.den)?;
+        let num = self.num.checked_mul(other.den / g)?.checked_add(other.num.checked_mul(self.den / g)?)?;
+        Rational::new(num, den)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(Rational { num: other.num.checked_neg()?, den: other.den })
+    }
+
+    // cross-reduce first to keep the intermediates small
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        let g1 = gcd(self.num, other.den).max(1);
+        let g2 = gcd(other.num, self.den).max(1);
+        Rational::new(
+            (self.num / g1).checked_mul(other.num / g2)?,
+            (self.den / g2).checked_mul(other.den / g1)?,
+        )
+    }
+}
+
+impl Field for Rational {
+    fn checked_div(self, other: Self) -> Option<Self> {
+        if other.num == 0 {
+            return None;
+        }
+        self.checked_mul(Rational::new(other.den, other.num)?)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+// Newton forward-difference form of the polynomial through a sequence
+// sampled at x = 0, 1, 2, ...: y(x) = sum_k C(x, k) * diffs[k], where
+// diffs[k] is the first entry of the k-th difference row.
+#[derive(Debug, Clone)]
+struct Newton<T: Num> {
+    diffs: Vec<T>,
+}
+
+impl<T: Num> Newton<T> {
+    // Differences are taken until a row is constant with at least two
+    // entries to confirm it; a sequence that runs out first isn't
+    // determined by its samples
+    fn fit(vals: &[T]) -> Result<Newton<T>, String> {
+        if vals.len() < 2 {
+            return Err(String::from("need at least two samples"));
+        }
+        let mut row = vals.to_vec();
+        let mut diffs = vec![];
+        while row.len() >= 2 {
+            diffs.push(row[0]);
+            if row.iter().all(|&x| x == row[0]) {
+                return Ok(Newton { diffs });
+            }
+            row = row.windows(2)
+                .map(|w| w[1].checked_sub(w[0]))
+                .collect::<Option<Vec<T>>>()
+                .ok_or("overflow taking differences")?;
+        }
+        let vals = vals.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        Err(format!("differences of [{}] never become constant", vals.join(", ")))
+    }
+
+    fn degree(&self) -> usize {
+        self.diffs.len() - 1
+    }
+
+    // The value at any integer x, including before the first sample.
+    // C(x, k) is built up as C(x, k-1) * (x - k + 1) / k, which is always exact.
+    fn at(&self, x: i128) -> Result<T, String> {
+        let overflow = || format!("overflow evaluating at {}", x);
+        let mut binom: i128 = 1;
+        let mut sum = T::from_int(0);
+        for (k, &d) in self.diffs.iter().enumerate() {
+            if k > 0 {
+                binom = binom.checked_mul(x - k as i128 + 1).ok_or_else(overflow)? / k as i128;
+            }
+            sum = T::from_int(binom).checked_mul(d)
+                .and_then(|t| sum.checked_add(t))
+                .ok_or_else(overflow)?;
+        }
+        Ok(sum)
+    }
+}
+
+// The polynomial through arbitrary (x, y) points, in Lagrange form:
+// y(x) = sum_i y_i * prod_{j != i} (x - x_j) / (x_i - x_j)
+#[derive(Debug, Clone)]
+struct Lagrange<T: Field> {
+    points: Vec<(T, T)>,
+}
+
+impl<T: Field> Lagrange<T> {
+    fn at(&self, x: T) -> Result<T, String> {
+        let overflow = || format!("overflow evaluating at {}", x);
+        let mut sum = T::from_int(0);
+        for (i, &(xi, yi)) in self.points.iter().enumerate() {
+            let mut term = yi;
+            for (j, &(xj, _)) in self.points.iter().enumerate() {
+                if i != j {
+                    let num = x.checked_sub(xj).ok_or_else(overflow)?;
+                    let den = xi.checked_sub(xj).ok_or_else(overflow)?;
+                    if den == T::from_int(0) {
+                        return Err(format!("two samples at x = {}", xi));
+                    }
+                    term = term.checked_mul(num).and_then(|t| t.checked_div(den)).ok_or_else(overflow)?;
+                }
+            }
+            sum = sum.checked_add(term).ok_or_else(overflow)?;
+        }
+        Ok(sum)
+    }
+}
+
+// One line of rational samples: "x:y" pairs, or bare y values at x = 0, 1, ...
+// Extrapolates one sample spacing past each end, plus optionally at x = `at`.
+fn rational_line(line: &str, at: Option<Rational>) -> Result<(Rational, Rational, Option<Rational>), String> {
+    let points = line.split_whitespace().enumerate().map(|(i, tok)| match tok.split_once(':') {
+        Some((x, y)) => Ok((Rational::from_str(x)?, Rational::from_str(y)?)),
+        None => Ok((Rational::from_int(i as i128), Rational::from_str(tok)?)),
+    }).collect::<Result<Vec<_>, String>>()?;
+    if points.len() < 2 {
+        return Err(String::from("need at least two samples"));
+    }
+
+    let overflow = || String::from("overflow");
+    let n = points.len();
+    let next = points[n-1].0.checked_add(points[n-1].0.checked_sub(points[n-2].0).ok_or_else(overflow)?).ok_or_else(overflow)?;
+    let prev = points[0].0.checked_sub(points[1].0.checked_sub(points[0].0).ok_or_else(overflow)?).ok_or_else(overflow)?;
+
+    let model = Lagrange { points };
+    Ok((model.at(next)?, model.at(prev)?, at.map(|x| model.at(x)).transpose()?))
+}
+
+fn solve_rational(input: impl BufRead, at: Option<Rational>) {
+    let zero = Rational::from_int(0);
+    let (mut next, mut prev, mut at_sum) = (zero, zero, zero);
+    for (i, line) in input.lines().enumerate() {
+        let sums = rational_line(&line.unwrap(), at).and_then(|(n, p, a)| {
+            let overflow = || String::from("sum overflows");
+            Ok((
+                next.checked_add(n).ok_or_else(overflow)?,
+                prev.checked_add(p).ok_or_else(overflow)?,
+                at_sum.checked_add(a.unwrap_or(zero)).ok_or_else(overflow)?,
+            ))
+        });
+        match sums {
+            Ok(s) => (next, prev, at_sum) = s,
+            Err(e) => println!("Skipping line {}: {}", i + 1, e),
+        }
+    }
+    println!("Next: {}", next);
+    println!("Previous: {}", prev);
+    if let Some(x) = at {
+        println!("At {}: {}", x, at_sum);
+    }
+}
+
+// One sequence's next and previous values added to the running sums of both parts
+fn add_ends(model: &Newton<i128>, len: usize, (p1, p2): (i128, i128)) -> Result<(i128, i128), String> {
+    let overflow = || String::from("sum overflows");
+    Ok((
+        p1.checked_add(model.at(len as i128)?).ok_or_else(overflow)?,
+        p2.checked_add(model.at(-1)?).ok_or_else(overflow)?,
+    ))
+}
+
+// Parts 1 and 2, and the sum `ahead` steps past each sequence's last
+// value (negative to go back)
+fn solve(input: impl BufRead, ahead: Option<i128>) -> (i128, i128, Option<i128>) {
+    let (mut p1, mut p2) = (0, 0);
+    let mut horizon = ahead.map(|_| 0);
+    for (i, line) in input.lines().enumerate() {
+        let v: Vec<i128> = line.unwrap().split_whitespace().map(|s| s.parse::<i128>().unwrap()).collect();
+        let model = match Newton::fit(&v) {
+            Ok(model) => model,
+            Err(e) => {
+                println!("Skipping line {}: {}", i + 1, e);
+                continue;
+            }
+        };
+
+        match add_ends(&model, v.len(), (p1, p2)) {
+            Ok((s1, s2)) => (p1, p2) = (s1, s2),
+            Err(e) => {
+                println!("Skipping line {}: {}", i + 1, e);
+                continue;
+            }
+        }
+        if let (Some(h), Some(n)) = (horizon.as_mut(), ahead) {
+            match model.at(v.len() as i128 - 1 + n)
+                .and_then(|y| h.checked_add(y).ok_or_else(|| String::from("sum overflows"))) {
+                Ok(sum) => *h = sum,
+                Err(e) => println!("Line {} (degree {}): {}", i + 1, model.degree(), e),
+            }
+        }
+    }
+    (p1, p2, horizon)
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    input: Option<String>,  // path, or "-" for stdin
+    ahead: Option<i128>,
+    rational: bool,
+    at: Option<Rational>,
+}
+
+// day09 [input] [--ahead N] [--rational [--at X]]
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut it = env::args().skip(1);
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--ahead" => args.ahead = Some(it.next().expect("--ahead needs a step count").parse::<i128>().unwrap()),
+            "--rational" => args.rational = true,
+            "--at" => args.at = Some(Rational::from_str(&it.next().expect("--at needs an x")).unwrap()),
+            _ => args.input = Some(a),
+        }
+    }
+    args
+}
+
+fn open_input(dayX: &str, root: &str, input: Option<&str>) -> Box<dyn BufRead> {
+    match input {
+        Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(File::open(path).unwrap())),
+        None => {
+            let path = String::from(root) + "/src/" + dayX + "/input.txt";
+            // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
+            Box::new(BufReader::new(File::open(path).unwrap()))
+        }
+    }
 }
 
 fn main() {
     let dayX = env!("CARGO_BIN_NAME");
     let root = env!("CARGO_MANIFEST_DIR");
 
-    let path = String::from(root) + "/src/" + dayX + "/input.txt";
-    // let path = String::from(root) + "/src/" + dayX + "/test1.txt";
-    let txt = fs::read_to_string(path).unwrap();
+    let args = parse_args();
 
     println!("This is {}", dayX);
-    println!("Part 1: {:?}", part1(&txt));
-    println!("Part 2: {:?}", part2(&txt));
+    if args.rational {
+        solve_rational(open_input(dayX, root, args.input.as_deref()), args.at);
+        return;
+    }
+
+    let (p1, p2, horizon) = solve(open_input(dayX, root, args.input.as_deref()), args.ahead);
+    println!("Part 1: {:?}", p1);
+    println!("Part 2: {:?}", p2);
+    if let (Some(h), Some(n)) = (horizon, args.ahead) {
+        println!("{} steps ahead: {:?}", n, h);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sums(txt: &str, ahead: i128) -> (i128, i128, Option<i128>) {
+        solve(txt.as_bytes(), Some(ahead))
+    }
+
+    #[test]
+    fn example() {
+        let txt = include_str!("test1.txt");
+        assert_eq!(sums(txt, 1), (114, 2, Some(114)));
+        assert_eq!(sums(txt, 0).2, Some(15 + 21 + 45));
+        // backwards from the last value, into the samples and past the start
+        assert_eq!(sums(txt, -3).2, Some(28));
+        assert_eq!(sums(txt, -6).2, Some(2));
+    }
+
+    #[test]
+    fn newton_at() {
+        let model = Newton::fit(&[1i128, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(model.degree(), 2);
+        assert_eq!(model.at(6), Ok(28));
+        assert_eq!(model.at(-1), Ok(0));
+        assert_eq!(model.at(-4), Ok(3));
+        assert!(model.at(i128::MAX / 2).is_err());
+    }
+
+    #[test]
+    fn too_few_samples() {
+        for vals in [&[][..], &[5i128]] {
+            assert_eq!(Newton::fit(vals).unwrap_err(), "need at least two samples");
+        }
+        assert!(Newton::fit(&[1i128, 2, 4]).unwrap_err().contains("never become constant"));
+    }
+
+    fn q(s: &str) -> Rational {
+        Rational::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn rational_parsing() {
+        assert_eq!(q("-7/2"), Rational::new(-7, 2).unwrap());
+        assert_eq!(q("1.25"), q("5/4"));
+        assert_eq!(q("-0.5"), q("-1/2"));
+        assert_eq!(q(".5"), q("1/2"));
+        assert_eq!(q("6/-4").to_string(), "-3/2");
+        for bad in ["x", "1/0", "1.2.3", "1.-5", &format!("0.{}", "1".repeat(39))] {
+            assert!(Rational::from_str(bad).is_err(), "{}", bad);
+        }
+    }
+
+    // y = x^2 through fractional samples, extrapolated exactly
+    #[test]
+    fn lagrange_is_exact() {
+        let (next, prev, at) = rational_line("0:0 0.5:0.25 1:1", Some(q("1/3"))).unwrap();
+        assert_eq!((next, prev, at), (q("9/4"), q("1/4"), Some(q("1/9"))));
+        assert!(rational_line("1 2 x", None).is_err());
+        assert!(rational_line("1:1 1:2", None).unwrap_err().contains("two samples at x = 1"));
+    }
+
+    #[test]
+    fn rational_matches_integer() {
+        let txt = include_str!("test1.txt");
+        for line in txt.lines() {
+            let v = line.split_whitespace().map(|s| s.parse::<i128>().unwrap()).collect::<Vec<_>>();
+            let model = Newton::fit(&v).unwrap();
+            let (next, prev, _) = rational_line(line, None).unwrap();
+            assert_eq!(next, Rational::from_int(model.at(v.len() as i128).unwrap()));
+            assert_eq!(prev, Rational::from_int(model.at(-1).unwrap()));
+        }
+    }
 }
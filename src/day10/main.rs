@@ -2,144 +2,263 @@
 
 use std::fs;
 
+type Pos = (usize, usize);  // (row, col)
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    N,
+    E,
+    S,
+    W,
+}
+
+const DIRS: [Dir; 4] = [Dir::N, Dir::E, Dir::S, Dir::W];
+
+impl Dir {
+    fn opposite(&self) -> Dir {
+        match self {
+            Dir::N => Dir::S,
+            Dir::E => Dir::W,
+            Dir::S => Dir::N,
+            Dir::W => Dir::E,
+        }
+    }
+}
+
+// the two directions a pipe connects, if it is one
+fn connections(c: char) -> Option<[Dir; 2]> {
+    match c {
+        '|' => Some([Dir::N, Dir::S]),
+        '-' => Some([Dir::E, Dir::W]),
+        'L' => Some([Dir::N, Dir::E]),
+        'J' => Some([Dir::N, Dir::W]),
+        '7' => Some([Dir::S, Dir::W]),
+        'F' => Some([Dir::E, Dir::S]),
+        _ => None,
+    }
+}
+
+fn pipe_for(dirs: [Dir; 2]) -> char {
+    ['|', '-', 'L', 'J', '7', 'F'].into_iter()
+        .find(|&c| {
+            let conn = connections(c).unwrap();
+            conn.contains(&dirs[0]) && conn.contains(&dirs[1])
+        })
+        .unwrap()
+}
+
 struct Grid {
     data: Vec<Vec<char>>,
-    loop_path: Vec<(usize, usize)>,
-    S: (usize, usize),
-    N: usize,
+    S: Pos,
+    Ny: usize,
+    Nx: usize,
 }
 
-impl Grid { 
+impl Grid {
     fn new(txt: &str) -> Grid {
         let data: Vec<Vec<char>> = txt.lines().map(|line| line.chars().collect()).collect();
-        let N = data.len();
+        let Ny = data.len();
+        let Nx = data[0].len();
 
         let mut S = (0,0);
-        for j in 0..data.len() {
-            for i in 0..data[j].len() {
-                match data[j][i] {
-                    'S' => { S = (j,i); break; },
-                    _ => (),
-                }
+        for (j, row) in data.iter().enumerate() {
+            if let Some(i) = row.iter().position(|&c| c == 'S') {
+                S = (j, i);
             }
         }
-        Grid { data,
-            S,
-            N,
-            loop_path: Vec::new(),
-        }
+        Grid { data, S, Ny, Nx }
     }
 
-    fn get(&self, pos: (usize, usize)) -> char {
+    fn get(&self, pos: Pos) -> char {
         self.data[pos.0][pos.1]
     }
 
-    fn loop_circ(&self) -> (usize, Vec<(usize, usize)>) {
-        let mut prev = self.S;
-
-        // prime the pos
-        let mut pos: (usize, usize) = 
-            if prev.0 > 0 && ['|', 'F', '7'].contains(&self.data[prev.0-1][prev.1]) {
-                // up
-                (prev.0-1, prev.1)
-            } else if prev.0 < self.N-1 && ['|', 'J', 'L'].contains(&self.data[prev.0+1][prev.1]) {
-                // down
-                (prev.0+1, prev.1)
-            } else if prev.1 > 0 && ['-', 'F', 'L'].contains(&self.data[prev.0][prev.1-1]) {
-                // left
-                (prev.0, prev.1-1)
-            } else if prev.1 < self.N-1 && ['-', 'J', '7'].contains(&self.data[prev.0][prev.1+1]) {
-                // right
-                (prev.0, prev.1+1)
-            } else {
-                panic!("No start direction found!");
-            };
-            
-        let mut pathlen = 1;
-        let mut loop_path: Vec<(usize, usize)> = vec![self.S];
-        while pos != self.S {
-            let next = match self.get(pos) {
-                '|' => {
-                    if (pos.0 + 1, pos.1) == prev {
-                        (pos.0 - 1, pos.1)
-                    } else {
-                        (pos.0 + 1, pos.1)
-                    }
-                },
-                '-' => {
-                    if (pos.0, pos.1 + 1) == prev {
-                        (pos.0, pos.1 - 1)
-                    } else {
-                        (pos.0, pos.1 + 1)
-                    }
-                },
-                'L' => {
-                    if (pos.0 - 1, pos.1) == prev {
-                        (pos.0, pos.1 + 1)
-                    } else {
-                        (pos.0 - 1, pos.1)
-                    }
-                },
-                '7' => {
-                    if (pos.0, pos.1 - 1) == prev {
-                        (pos.0 + 1, pos.1)
-                    } else {
-                        (pos.0, pos.1 - 1)
-                    }
-                },
-                'F' => {
-                    if (pos.0 + 1, pos.1) == prev {
-                        (pos.0, pos.1 + 1)
-                    } else {
-                        (pos.0 + 1, pos.1)
-                    }
-                },
-                'J' => {
-                    if (pos.0, pos.1 - 1) == prev {
-                        (pos.0 - 1, pos.1)
-                    } else {
-                        (pos.0, pos.1 - 1)
-                    }
-                },
-                _ => panic!("Unknown char: {}", self.get(pos)),
-            };
-            prev = pos;
-            pos = next;
-            if ['L', 'F', '7', 'J', 'S'].contains(&self.get(pos)) {
-                loop_path.push(pos);
+    fn neighbor(&self, pos: Pos, dir: Dir) -> Option<Pos> {
+        match dir {
+            Dir::N if pos.0 > 0 => Some((pos.0 - 1, pos.1)),
+            Dir::S if pos.0 + 1 < self.Ny => Some((pos.0 + 1, pos.1)),
+            Dir::W if pos.1 > 0 => Some((pos.0, pos.1 - 1)),
+            Dir::E if pos.1 + 1 < self.Nx => Some((pos.0, pos.1 + 1)),
+            _ => None,
+        }
+    }
+
+    // The pipe under S, from the neighbors that connect back to it
+    fn infer_start(&self) -> Result<char, String> {
+        let dirs = DIRS.into_iter()
+            .filter(|&d| self.neighbor(self.S, d)
+                .and_then(|n| connections(self.get(n)))
+                .is_some_and(|conn| conn.contains(&d.opposite())))
+            .collect::<Vec<Dir>>();
+        match dirs.as_slice() {
+            &[a, b] => Ok(pipe_for([a, b])),
+            _ => Err(format!("S at {:?} has {} connecting neighbors {:?}, so its pipe is ambiguous",
+                self.S, dirs.len(), dirs)),
+        }
+    }
+
+    // Follow the pipes from S back around to S
+    fn find_loop(&self) -> Result<Loop, String> {
+        let start_tile = self.infer_start()?;
+        let tile = |pos: Pos| if pos == self.S { start_tile } else { self.get(pos) };
+
+        let mut cells = vec![];
+        let mut pos = self.S;
+        let mut dir = connections(start_tile).unwrap()[0];
+        loop {
+            cells.push((pos, dir));
+            pos = self.neighbor(pos, dir).ok_or(format!("Loop runs off the grid at {:?}", pos))?;
+            if pos == self.S {
+                break;
             }
-            pathlen += 1;
+            let conn = connections(tile(pos)).ok_or(format!("Loop breaks at {:?}", pos))?;
+            dir = if conn[0] == dir.opposite() { conn[1] } else { conn[0] };
         }
 
-        (pathlen, loop_path)
+        Ok(Loop { cells, start_tile })
+    }
+}
+
+// The main loop, in order from S, with the direction taken out of each cell
+#[derive(Debug, Clone)]
+struct Loop {
+    cells: Vec<(Pos, Dir)>,
+    start_tile: char,
+}
+
+impl Loop {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    // steps to the farthest point along the loop
+    fn farthest(&self) -> usize {
+        self.len() / 2
     }
 
+    // twice the enclosed area, treating the loop as a polygon through cell centers
     fn shoelace(&self) -> i64 {
+        let L = self.len();
         let mut sum = 0i64;
-        let L = self.loop_path.len() as i64;
-        for i in 0i64..L {
-            sum += self.loop_path[i as usize].0 as i64 * (
-                self.loop_path[(i+1).rem_euclid(L) as usize].1 as i64
-                - self.loop_path[(i-1).rem_euclid(L) as usize].1 as i64
-            );
+        for i in 0..L {
+            let (a, _) = self.cells[i];
+            let (b, _) = self.cells[(i + 1) % L];
+            sum += a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64;
         }
         sum.abs()
     }
+
+    // tiles strictly inside, by Pick's theorem: A = I + B/2 - 1
+    fn enclosed(&self) -> i64 {
+        (self.shoelace() - self.len() as i64) / 2 + 1
+    }
+
+    // which cells of the grid the loop passes through
+    fn mask(&self, grid: &Grid) -> Vec<Vec<bool>> {
+        let mut mask = vec![vec![false; grid.Nx]; grid.Ny];
+        for &(pos, _) in self.cells.iter() {
+            mask[pos.0][pos.1] = true;
+        }
+        mask
+    }
+
+    // Classify every tile by scanning each row and flipping inside/outside
+    // whenever we cross the loop. Only loop pipes that reach north count as
+    // crossings, so runs like F--J flip once and F--7 not at all.
+    fn classify(&self, grid: &Grid) -> Vec<Vec<Tile>> {
+        let mask = self.mask(grid);
+        (0..grid.Ny).map(|j| {
+            let mut inside = false;
+            (0..grid.Nx).map(|i| {
+                if !mask[j][i] {
+                    return if inside { Tile::Inside } else { Tile::Outside };
+                }
+                let c = if (j, i) == grid.S { self.start_tile } else { grid.get((j, i)) };
+                if connections(c).unwrap().contains(&Dir::N) {
+                    inside = !inside;
+                }
+                Tile::Loop
+            }).collect()
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Loop,
+    Inside,
+    Outside,
+}
+
+// The grid with loop pipes in bold yellow, inside tiles as a green I,
+// and outside tiles dimmed
+fn show_classified(grid: &Grid, tiles: &[Vec<Tile>]) {
+    for (j, row) in tiles.iter().enumerate() {
+        let line = row.iter().enumerate().map(|(i, t)| match t {
+            Tile::Loop => format!("\x1b[1;33m{}\x1b[0m", grid.get((j, i))),
+            Tile::Inside => String::from("\x1b[1;32mI\x1b[0m"),
+            Tile::Outside => format!("\x1b[2m{}\x1b[0m", grid.get((j, i))),
+        }).collect::<String>();
+        println!("{}", line);
+    }
+}
+
+// box-drawing glyph for a pipe tile
+fn glyph(c: char) -> char {
+    match c {
+        '|' => '│',
+        '-' => '─',
+        'L' => '└',
+        'J' => '┘',
+        '7' => '┐',
+        'F' => '┌',
+        _ => c,
+    }
+}
+
+// The maze in box-drawing glyphs with S drawn as the pipe it stands for.
+// With color, S is bold red and everything off the loop is dimmed;
+// without, off-loop tiles become '·' so a plain text file stays readable.
+fn render(grid: &Grid, lp: &Loop, color: bool) -> String {
+    let mask = lp.mask(grid);
+    let mut out = String::new();
+    for (j, row) in mask.iter().enumerate() {
+        for (i, &on_loop) in row.iter().enumerate() {
+            let g = glyph(grid.get((j, i)));
+            if (j, i) == grid.S {
+                let g = glyph(lp.start_tile);
+                if color {
+                    out += &format!("\x1b[1;31m{}\x1b[0m", g);
+                } else {
+                    out.push(g);
+                }
+            } else if on_loop {
+                out.push(g);
+            } else if color {
+                out += &format!("\x1b[2m{}\x1b[0m", g);
+            } else {
+                out.push('·');
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn count_inside(tiles: &[Vec<Tile>]) -> i64 {
+    tiles.iter().flatten().filter(|&&t| t == Tile::Inside).count() as i64
 }
 
 fn part1(txt: &str) -> i64 {
     let grid = Grid::new(txt);
 
-    (grid.loop_circ().0 / 2) as i64
+    grid.find_loop().unwrap().farthest() as i64
 }
 
 fn part2(txt: &str) -> i64 {
-    let mut grid = Grid::new(txt);
+    let grid = Grid::new(txt);
 
-    let p1: (usize, Vec<(usize, usize)>) = grid.loop_circ();
-    let circ = p1.0;
-    grid.loop_path = p1.1;
-    (grid.shoelace() - circ as i64 + 1) / 2 + 1
+    grid.find_loop().unwrap().enclosed()
 }
 
 fn main() {
@@ -158,4 +277,59 @@ fn main() {
     println!("This is {}", dayX);
     println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));
+
+    let args: Vec<String> = std::env::args().collect();
+    let show = args.iter().any(|a| a == "--show");
+    let render_at = args.iter().position(|a| a == "--render");
+    if show || render_at.is_some() {
+        let grid = Grid::new(&txt);
+        let lp = grid.find_loop().unwrap();
+
+        // --show marks every tile as loop, inside or outside,
+        // and checks the scanline against Pick's theorem
+        if show {
+            let tiles = lp.classify(&grid);
+            show_classified(&grid, &tiles);
+            let (scanline, pick) = (count_inside(&tiles), lp.enclosed());
+            println!("Inside tiles: {} by scanline, {} by Pick's theorem ({})", scanline, pick,
+                if scanline == pick { "agree" } else { "DISAGREE" });
+        }
+
+        // --render draws the maze with box-drawing glyphs,
+        // --render FILE writes an uncolored copy to FILE instead
+        if let Some(i) = render_at {
+            match args.get(i+1).filter(|a| !a.starts_with("--")) {
+                Some(file) => fs::write(file, render(&grid, &lp, false)).unwrap(),
+                None => print!("{}", render(&grid, &lp, true)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the documented answers for the part 2 examples, both ways
+    #[test]
+    fn examples() {
+        let cases = [
+            (include_str!("test3.txt"), 4),
+            (include_str!("test4.txt"), 8),
+            (include_str!("test5.txt"), 10),
+        ];
+        for (txt, expected) in cases {
+            let grid = Grid::new(txt);
+            let lp = grid.find_loop().unwrap();
+            assert_eq!(count_inside(&lp.classify(&grid)), expected);
+            assert_eq!(lp.enclosed(), expected);
+        }
+    }
+
+    #[test]
+    fn scanline_matches_pick_on_input() {
+        let grid = Grid::new(include_str!("input.txt"));
+        let lp = grid.find_loop().unwrap();
+        assert_eq!(count_inside(&lp.classify(&grid)), lp.enclosed());
+    }
 }
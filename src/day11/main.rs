@@ -44,6 +44,7 @@ impl Universe {
         }
     }
 
+    #[cfg(test)]
     fn pair_dist(&self, j: usize, i: usize) -> usize {
         let res = ((self.gals[j].0 as i64 - self.gals[i].0 as i64).abs() +
         (self.gals[j].1 as i64 - self.gals[i].1 as i64).abs())
@@ -51,24 +52,40 @@ impl Universe {
         // println!("{} {} {}", j, i, res);
         res
     }
+
+    // O(n^2): every pair separately, kept as the reference for total_dist
+    #[cfg(test)]
+    fn pairwise_dist(&self) -> usize {
+        let N = self.gals.len();
+        (0..N).map(|j| {
+            (j+1..N).map(|i| self.pair_dist(j, i))
+                .sum::<usize>()
+        }).sum()
+    }
+
+    // O(n log n): Manhattan distance splits per axis, and along one sorted
+    // axis the k-th coordinate is ahead of the k before it by x*k - (sum of those)
+    fn total_dist(&self) -> usize {
+        let axis = |mut xs: Vec<usize>| -> usize {
+            xs.sort_unstable();
+            let mut prefix = 0;
+            xs.iter().enumerate().map(|(k, &x)| {
+                let d = x * k - prefix;
+                prefix += x;
+                d
+            }).sum()
+        };
+        axis(self.gals.iter().map(|g| g.0).collect()) +
+            axis(self.gals.iter().map(|g| g.1).collect())
+    }
 }
 
 fn part1(txt: &str) -> usize {
-    let u = Universe::new(txt, 2);
-    let N = u.gals.len();
-    (0..N).map(|j| {
-        (j+1..N).map(|i| u.pair_dist(j, i))
-            .sum::<usize>()
-    }).sum()
+    Universe::new(txt, 2).total_dist()
 }
 
 fn part2(txt: &str) -> usize {
-    let u = Universe::new(txt, 1000000);
-    let N = u.gals.len();
-    (0..N).map(|j| {
-        (j+1..N).map(|i| u.pair_dist(j, i))
-            .sum::<usize>()
-    }).sum()
+    Universe::new(txt, 1000000).total_dist()
 }
 
 fn main() {
@@ -83,3 +100,20 @@ fn main() {
     println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_sums_match_pairwise() {
+        let txt = include_str!("test1.txt");
+        for expansion in [1, 2, 10, 100, 1000000] {
+            let u = Universe::new(txt, expansion);
+            assert_eq!(u.total_dist(), u.pairwise_dist(), "expansion {}", expansion);
+        }
+        assert_eq!(Universe::new(txt, 2).total_dist(), 374);
+        assert_eq!(Universe::new(txt, 10).total_dist(), 1030);
+        assert_eq!(Universe::new(txt, 100).total_dist(), 8410);
+    }
+}
@@ -124,6 +124,41 @@ impl Machine {
         }
     }
 
+    // Graphviz: flip-flops are boxes, conjunctions are inverted triangles,
+    // the broadcaster is a double circle, and untyped sinks are plain text
+    fn to_dot(&self) -> String {
+        let mut names = self.modules.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut dot = String::from("digraph machine {\n");
+        dot += "    button [shape=point];\n";
+        dot += "    button -> broadcaster;\n";
+        for name in names.iter() {
+            let m = &self.modules[*name];
+            let style = match m.kind {
+                Kind::Bcast => "shape=doublecircle, style=filled, fillcolor=lightgrey",
+                Kind::Flip => "shape=box, label=\"%NAME\"",
+                Kind::Conj => "shape=invtriangle, style=filled, fillcolor=lightblue, label=\"&NAME\"",
+            };
+            dot += &format!("    \"{}\" [{}];\n", name, style.replace("NAME", name));
+        }
+
+        let mut sinks = vec![];
+        for name in names.iter() {
+            for o in self.modules[*name].outputs.iter() {
+                if !self.modules.contains_key(o) && !sinks.contains(&o) {
+                    sinks.push(o);
+                }
+                dot += &format!("    \"{}\" -> \"{}\";\n", name, o);
+            }
+        }
+        for o in sinks {
+            dot += &format!("    \"{}\" [shape=plaintext];\n", o);
+        }
+        dot += "}\n";
+        dot
+    }
+
     fn score(&self) -> usize {
         (0..=1).map(|i| self.modules.iter().map(|(_,m)| m.sent[i]).sum::<usize>())
             .product()
@@ -186,6 +221,15 @@ fn main() {
     // let path = String::from(root) + "/src/" + dayX + "/test2.txt";
     let txt = fs::read_to_string(path).unwrap();
 
+    // --dot FILE writes the module graph for Graphviz
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--dot") {
+        match args.get(i+1) {
+            Some(file) => fs::write(file, Machine::new(&txt).to_dot()).unwrap(),
+            None => println!("Usage: --dot FILE"),
+        }
+    }
+
     println!("This is {}", dayX);
     println!("Part 1: {:?}", part1(&txt));
     println!("Part 2: {:?}", part2(&txt));